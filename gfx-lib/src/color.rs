@@ -65,6 +65,34 @@ impl Color {
         }
     }
 
+    /// Create a color from float components, each in the range 0..1.
+    pub fn rgba_f32(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color {
+            r: r.max(0.0).min(1.0),
+            g: g.max(0.0).min(1.0),
+            b: b.max(0.0).min(1.0),
+            a: a.max(0.0).min(1.0),
+        }
+    }
+
+    /// Copy of this color with a different alpha, clamped to 0..1.
+    pub fn with_alpha(self, a: f32) -> Color {
+        Color {
+            a: a.max(0.0).min(1.0),
+            ..self
+        }
+    }
+
+    /// Multiply the color channels by `tint`, leaving alpha alone.
+    pub fn tinted(self, tint: Color) -> Color {
+        Color {
+            r: self.r * tint.r,
+            g: self.g * tint.g,
+            b: self.b * tint.b,
+            a: self.a,
+        }
+    }
+
     pub fn data(&self) -> [f32; 4] {
         [self.r, self.g, self.b, self.a]
     }
@@ -75,3 +103,22 @@ impl Default for Color {
         COLOR_WHITE
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_alpha_clamps() {
+        assert_eq!(COLOR_WHITE.with_alpha(1.5).a, 1.0);
+        assert_eq!(COLOR_WHITE.with_alpha(-0.5).a, 0.0);
+        assert_eq!(COLOR_WHITE.with_alpha(0.25).a, 0.25);
+    }
+
+    #[test]
+    fn with_alpha_keeps_color() {
+        let color = Color::rgba_f32(0.1, 0.2, 0.3, 1.0).with_alpha(0.5);
+
+        assert_eq!((color.r, color.g, color.b), (0.1, 0.2, 0.3));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::input::{InputState, MouseButton};
+use ::winit::{dpi::PhysicalPosition, event::ElementState};
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+// How far the cursor moves per second with the stick all the way over, in render units
+const CURSOR_SPEED: f64 = 400.0;
+
+// Stick deflection below this is treated as no input
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+/// Drives the mouse cursor from a gamepad, so anything that works with the mouse works with a gamepad too.
+///
+/// The left stick and d-pad move a virtual cursor, which is fed to `InputState` as cursor movement.
+/// The A (south) button is fed to `InputState` as the left mouse button.
+///
+/// The stick uses a radial dead zone: the stick's deflection is treated as a vector, and if its length is
+/// under `STICK_DEAD_ZONE` it is ignored entirely. Past the dead zone, the length is rescaled from
+/// `STICK_DEAD_ZONE..1` to `0..1`, keeping the direction. This hides drift from worn sticks without
+/// snapping to the axes the way a per-axis dead zone does, and the cursor still starts moving slowly
+/// right at the edge of the dead zone instead of jumping.
+pub struct GamepadCursor {
+    gilrs: Option<Gilrs>,
+    stick: (f32, f32),
+    // Virtual cursor position in physical pixels, once a gamepad has moved it
+    position: Option<(f64, f64)>,
+}
+
+impl GamepadCursor {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                eprintln!("[Gamepad] Failed to initialize gamepad support: {}", e);
+                None
+            }
+        };
+
+        GamepadCursor {
+            gilrs,
+            stick: (0.0, 0.0),
+            position: None,
+        }
+    }
+
+    /// Poll gamepad events and move the virtual cursor.
+    /// `scale` is the number of physical pixels per render unit, and `bounds` is the window size in physical pixels.
+    pub fn update(&mut self, dt: f64, scale: f64, bounds: (u32, u32), input_state: &mut InputState) {
+        let gilrs = match self.gilrs.as_mut() {
+            Some(gilrs) => gilrs,
+            None => return,
+        };
+
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(Button::South, _) => {
+                    input_state.handle_mouse_input(ElementState::Pressed, MouseButton::Left);
+                }
+                EventType::ButtonReleased(Button::South, _) => {
+                    input_state.handle_mouse_input(ElementState::Released, MouseButton::Left);
+                }
+                EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                    self.stick.0 = value;
+                }
+                EventType::AxisChanged(Axis::LeftStickY, value, _) => {
+                    self.stick.1 = value;
+                }
+                EventType::Disconnected => {
+                    self.stick = (0.0, 0.0);
+                }
+                _ => {}
+            }
+        }
+
+        let (mut x, mut y) = apply_dead_zone(self.stick);
+
+        // The d-pad moves at full speed, on top of the stick
+        for (_, gamepad) in gilrs.gamepads() {
+            if gamepad.is_pressed(Button::DPadLeft) {
+                x -= 1.0;
+            }
+            if gamepad.is_pressed(Button::DPadRight) {
+                x += 1.0;
+            }
+            if gamepad.is_pressed(Button::DPadUp) {
+                y += 1.0;
+            }
+            if gamepad.is_pressed(Button::DPadDown) {
+                y -= 1.0;
+            }
+        }
+
+        if (x == 0.0) && (y == 0.0) {
+            return;
+        }
+
+        // Start from wherever the mouse last left the cursor
+        let (cursor_x, cursor_y) = self.position.unwrap_or_else(|| {
+            let pos = input_state.cursor_pos();
+            (pos.x, pos.y)
+        });
+
+        // Stick up is positive, but screen space grows downwards
+        let distance = CURSOR_SPEED * scale * dt;
+        let new_x = (cursor_x + (x.max(-1.0).min(1.0) as f64 * distance)).max(0.0).min(bounds.0 as f64);
+        let new_y = (cursor_y - (y.max(-1.0).min(1.0) as f64 * distance)).max(0.0).min(bounds.1 as f64);
+
+        self.position = Some((new_x, new_y));
+        input_state.handle_cursor_movement(PhysicalPosition::new(new_x, new_y));
+    }
+
+    /// Forget the virtual cursor position, so the next gamepad movement starts from the mouse cursor.
+    pub fn handle_mouse_moved(&mut self) {
+        self.position = None;
+    }
+}
+
+fn apply_dead_zone((x, y): (f32, f32)) -> (f32, f32) {
+    let length = ((x * x) + (y * y)).sqrt();
+    if length < STICK_DEAD_ZONE {
+        return (0.0, 0.0);
+    }
+
+    let scaled_length = ((length - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE)).min(1.0);
+    (x / length * scaled_length, y / length * scaled_length)
+}
@@ -1,30 +1,101 @@
-use crate::Point2d;
-use ::winit::{event::ElementState, event::KeyboardInput, dpi::PhysicalPosition};
-use std::collections::HashMap;
+use crate::{window::DeltaTime, Point2d};
+use ::winit::{
+    dpi::PhysicalPosition,
+    event::ElementState,
+    event::KeyboardInput,
+    event::ModifiersState,
+    event::MouseScrollDelta,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 pub use ::winit::event::{MouseButton, VirtualKeyCode};
 
+// Pixel scroll deltas (from touchpads) are converted to lines using this
+const PIXELS_PER_SCROLL_LINE: f32 = 16.0;
+
+// A key or button change, waiting for a tick to see it
+#[derive(Clone, Copy)]
+enum QueuedInput {
+    Key(VirtualKeyCode, ElementState),
+    MouseButton(MouseButton, ElementState),
+    FocusLost,
+}
+
+/// Keyboard and mouse state, as seen by a fixed timestep tick.
+///
+/// Key and button events are queued as they arrive, and applied at the start of each tick by `begin_tick`.
+/// Each key or button changes state at most once per tick, so a press is seen by exactly one tick, and a
+/// press and release that arrive between the same two ticks are split across two ticks instead of being lost.
+/// Anything not applied yet carries over to the next tick. Frames that run no ticks never lose input, and
+/// frames that run several don't count the same press twice.
 #[derive(Default, Clone)]
 pub struct InputState {
+    queued_inputs: VecDeque<QueuedInput>,
     current_keys: HashMap<VirtualKeyCode, bool>,
     pressed_keys: HashMap<VirtualKeyCode, bool>,
     released_keys: HashMap<VirtualKeyCode, bool>,
+    // How long each held key has been held, in seconds. A key's time starts at 0 on the tick it's pressed.
+    key_held_durations: HashMap<VirtualKeyCode, DeltaTime>,
     current_mouse_buttons: HashMap<MouseButton, bool>,
     pressed_mouse_buttons: HashMap<MouseButton, bool>,
     released_mouse_buttons: HashMap<MouseButton, bool>,
     cursor_pos: Option<Point2d>,
+    scroll_delta: f32,
+    modifiers: ModifiersState,
 }
 
 impl InputState {
     pub fn new() -> InputState {
         InputState {
+            queued_inputs: VecDeque::new(),
             current_keys: HashMap::new(),
             pressed_keys: HashMap::new(),
             released_keys: HashMap::new(),
+            key_held_durations: HashMap::new(),
             current_mouse_buttons: HashMap::new(),
             pressed_mouse_buttons: HashMap::new(),
             released_mouse_buttons: HashMap::new(),
             cursor_pos: None,
+            scroll_delta: 0.0,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    /// Apply queued key and button changes, stopping at the first one for a key or button that already changed this tick.
+    /// `dt` is the length of a tick, and is added to the held time of every key that was already held.
+    pub fn begin_tick(&mut self, dt: DeltaTime) {
+        for duration in self.key_held_durations.values_mut() {
+            *duration += dt;
+        }
+
+        let mut changed_keys: HashSet<VirtualKeyCode> = HashSet::new();
+        let mut changed_mouse_buttons: HashSet<MouseButton> = HashSet::new();
+
+        while let Some(input) = self.queued_inputs.front().cloned() {
+            match input {
+                QueuedInput::Key(keycode, state) => {
+                    if !changed_keys.insert(keycode) {
+                        break;
+                    }
+
+                    self.apply_key(keycode, state);
+                }
+                QueuedInput::MouseButton(button, state) => {
+                    if !changed_mouse_buttons.insert(button) {
+                        break;
+                    }
+
+                    self.apply_mouse_button(button, state);
+                }
+                QueuedInput::FocusLost => {
+                    // The window ignores the synthetic releases sent on focus loss, so this is where held keys end
+                    self.current_keys.clear();
+                    self.key_held_durations.clear();
+                    self.current_mouse_buttons.clear();
+                }
+            }
+
+            self.queued_inputs.pop_front();
         }
     }
 
@@ -33,15 +104,21 @@ impl InputState {
         self.released_keys.clear();
         self.pressed_mouse_buttons.clear();
         self.released_mouse_buttons.clear();
+        self.scroll_delta = 0.0;
     }
 
     pub fn handle_keyboard_input(&mut self, input: &KeyboardInput) {
-        let keycode: VirtualKeyCode = input.virtual_keycode.unwrap();
+        if let Some(keycode) = input.virtual_keycode {
+            self.queued_inputs.push_back(QueuedInput::Key(keycode, input.state));
+        }
+    }
 
-        match input.state {
+    fn apply_key(&mut self, keycode: VirtualKeyCode, state: ElementState) {
+        match state {
             ElementState::Pressed => {
                 if !self.is_key_held(keycode) {
                     self.pressed_keys.insert(keycode, true);
+                    self.key_held_durations.insert(keycode, 0.0);
                 }
 
                 self.current_keys.insert(keycode, true);
@@ -49,11 +126,16 @@ impl InputState {
             ElementState::Released => {
                 self.released_keys.insert(keycode, true);
                 self.current_keys.insert(keycode, false);
+                self.key_held_durations.remove(&keycode);
             }
         }
     }
 
     pub fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        self.queued_inputs.push_back(QueuedInput::MouseButton(button, state));
+    }
+
+    fn apply_mouse_button(&mut self, button: MouseButton, state: ElementState) {
         match state {
             ElementState::Pressed => {
                 if !self.is_mouse_button_held(button) {
@@ -73,10 +155,38 @@ impl InputState {
         self.cursor_pos = Some(Point2d::new(position.x, position.y));
     }
 
+    pub fn handle_scroll(&mut self, delta: MouseScrollDelta) {
+        self.scroll_delta += match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / PIXELS_PER_SCROLL_LINE,
+        };
+    }
+
+    pub fn handle_modifiers_changed(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    /// Forget held keys, buttons, and modifiers. Used when the window loses focus, since we won't see them get released.
+    pub fn handle_focus_lost(&mut self) {
+        self.queued_inputs.push_back(QueuedInput::FocusLost);
+        self.modifiers = ModifiersState::empty();
+    }
+
+    /// Vertical scroll since the last tick, in lines. Positive is away from the user.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Cursor position in physical pixels, as reported by the window.
     pub fn cursor_pos(&self) -> Point2d {
         self.cursor_pos.unwrap_or(Point2d::origin())
     }
 
+    /// Cursor position in render units, matching what the renderer's projection draws.
+    pub fn cursor_pos_logical(&self, scale_factor: f32, render_scale: f32) -> Point2d {
+        self.cursor_pos() / (scale_factor * render_scale) as f64
+    }
+
     #[allow(dead_code)]
     pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
         *self.pressed_mouse_buttons.get(&button).unwrap_or(&false)
@@ -97,6 +207,31 @@ impl InputState {
         *self.pressed_keys.get(&keycode).unwrap_or(&false)
     }
 
+    /// Keys that were pressed this frame, sorted by keycode so the order is the same every run.
+    pub fn pressed_keys_iter(&self) -> impl Iterator<Item = VirtualKeyCode> {
+        let mut keys: Vec<VirtualKeyCode> = self
+            .pressed_keys
+            .iter()
+            .filter(|(_, pressed)| **pressed)
+            .map(|(keycode, _)| *keycode)
+            .collect();
+        keys.sort();
+
+        keys.into_iter()
+    }
+
+    /// Whether any key was pressed this frame.
+    #[allow(dead_code)]
+    pub fn any_key_pressed(&self) -> bool {
+        self.pressed_keys.values().any(|pressed| *pressed)
+    }
+
+    /// Whether any mouse button was pressed this frame.
+    #[allow(dead_code)]
+    pub fn any_mouse_pressed(&self) -> bool {
+        self.pressed_mouse_buttons.values().any(|pressed| *pressed)
+    }
+
     #[allow(dead_code)]
     pub fn is_key_released(&self, keycode: VirtualKeyCode) -> bool {
         *self.released_keys.get(&keycode).unwrap_or(&false)
@@ -106,4 +241,119 @@ impl InputState {
     pub fn is_key_held(&self, keycode: VirtualKeyCode) -> bool {
         *self.current_keys.get(&keycode).unwrap_or(&false)
     }
+
+    /// Whether a key has been held down for at least `secs` seconds of ticks.
+    #[allow(dead_code)]
+    pub fn is_key_held_for(&self, keycode: VirtualKeyCode, secs: DeltaTime) -> bool {
+        self.key_held_durations
+            .get(&keycode)
+            .map_or(false, |duration| *duration >= secs)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_shift_held(&self) -> bool {
+        self.modifiers.shift()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_ctrl_held(&self) -> bool {
+        self.modifiers.ctrl()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_alt_held(&self) -> bool {
+        self.modifiers.alt()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_logo_held(&self) -> bool {
+        self.modifiers.logo()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `modifiers` is deprecated in favor of ModifiersChanged, but still has to be filled in
+    #[allow(deprecated)]
+    fn key_event(input: &mut InputState, keycode: VirtualKeyCode, state: ElementState) {
+        input.handle_keyboard_input(&KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(keycode),
+            modifiers: ModifiersState::empty(),
+        });
+    }
+
+    fn press(input: &mut InputState, keycode: VirtualKeyCode) {
+        key_event(input, keycode, ElementState::Pressed);
+    }
+
+    fn release(input: &mut InputState, keycode: VirtualKeyCode) {
+        key_event(input, keycode, ElementState::Released);
+    }
+
+    // Run one tick the way the window loop does, returning whether the key was pressed and released during it
+    fn tick(input: &mut InputState, keycode: VirtualKeyCode) -> (bool, bool) {
+        input.begin_tick(1.0 / 60.0);
+        let seen = (input.is_key_pressed(keycode), input.is_key_released(keycode));
+        input.clear_pressed_and_released();
+
+        seen
+    }
+
+    #[test]
+    fn pressed_keys_iter_is_sorted() {
+        let mut input = InputState::new();
+        for keycode in [VirtualKeyCode::Key3, VirtualKeyCode::Escape, VirtualKeyCode::Key1, VirtualKeyCode::A].iter() {
+            press(&mut input, *keycode);
+        }
+        input.begin_tick(0.0);
+
+        let keys: Vec<VirtualKeyCode> = input.pressed_keys_iter().collect();
+        assert_eq!(
+            keys,
+            vec![VirtualKeyCode::Key1, VirtualKeyCode::Key3, VirtualKeyCode::A, VirtualKeyCode::Escape]
+        );
+    }
+
+    #[test]
+    fn press_and_release_before_a_tick_are_split_across_two_ticks() {
+        let mut input = InputState::new();
+        press(&mut input, VirtualKeyCode::Space);
+        release(&mut input, VirtualKeyCode::Space);
+
+        assert_eq!(tick(&mut input, VirtualKeyCode::Space), (true, false));
+        assert_eq!(tick(&mut input, VirtualKeyCode::Space), (false, true));
+        assert!(!input.is_key_held(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn press_is_seen_by_one_tick_only() {
+        let mut input = InputState::new();
+        press(&mut input, VirtualKeyCode::Space);
+
+        // Two ticks in the same frame, with no new events between them
+        assert_eq!(tick(&mut input, VirtualKeyCode::Space), (true, false));
+        assert_eq!(tick(&mut input, VirtualKeyCode::Space), (false, false));
+        assert!(input.is_key_held(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn focus_lost_clears_held_keys() {
+        let mut input = InputState::new();
+        press(&mut input, VirtualKeyCode::W);
+        tick(&mut input, VirtualKeyCode::W);
+        assert!(input.is_key_held(VirtualKeyCode::W));
+
+        input.handle_focus_lost();
+        tick(&mut input, VirtualKeyCode::W);
+        assert!(!input.is_key_held(VirtualKeyCode::W));
+        assert!(!input.is_key_held_for(VirtualKeyCode::W, 0.0));
+
+        // Pressing it again after regaining focus counts as a new press
+        press(&mut input, VirtualKeyCode::W);
+        assert_eq!(tick(&mut input, VirtualKeyCode::W), (true, false));
+    }
 }
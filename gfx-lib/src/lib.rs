@@ -8,11 +8,15 @@ extern crate gfx_backend_metal as backend;
 extern crate gfx_backend_vulkan as backend;
 
 extern crate gfx_hal;
+#[cfg(feature = "gamepad")]
+extern crate gilrs;
 pub extern crate image;
 extern crate nalgebra_glm as glm;
 extern crate winit;
 
 pub mod color;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
 pub mod input;
 pub mod mesh;
 pub mod renderer;
@@ -8,6 +8,9 @@ pub struct Vertex {
     pub uv: [f32; 2],
 }
 
+// Ends the current strip, when drawing with a line strip primitive
+pub const PRIMITIVE_RESTART_INDEX: u32 = std::u32::MAX;
+
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
@@ -26,52 +29,50 @@ pub fn add_sprite(
     y: f32,
     origin: Point2f,
     scale: Vector2f,
+    rotation: f32,
     color: Color,
     region: SpriteRegion,
-    spritesheet_width: u32,
-    spritesheet_height: u32,
+    uvs: [(f32, f32); 4],
 ) {
     let vertex_count: u32 = mesh.vertices.len() as u32;
     let color: [f32; 4] = color.data();
 
-    // TODO
-    // need to pre compute these uvs
-
-    let u: f32 = region.x as f32 / spritesheet_width as f32;
-    let v: f32 = region.y as f32 / spritesheet_height as f32;
-    let u_width: f32 = region.w as f32 / spritesheet_width as f32;
-    let v_height: f32 = region.h as f32 / spritesheet_height as f32;
+    // Corner offsets relative to the sprite origin
+    let left = -(origin.x as f32 * scale.x);
+    let top = -(origin.y as f32 * scale.y);
+    let right = left + (region.w as f32 * scale.x);
+    let bottom = top + (region.h as f32 * scale.y);
 
-    // Offset the render position based on the sprite origin
-    let x = x - (origin.x as f32 * scale.x);
-    let y = y - (origin.y as f32 * scale.y);
-    let w = region.w as f32 * scale.x;
-    let h = region.h as f32 * scale.y;
+    // Rotate each corner around the origin, then offset by the render position
+    let (sin, cos) = rotation.sin_cos();
+    let corner = |cx: f32, cy: f32| -> [f32; 3] {
+        [x + (cx * cos) - (cy * sin), y + (cx * sin) + (cy * cos), 0.0]
+    };
 
     let new_vertices: [Vertex; 4] = [
         // Top left
         Vertex {
-            position: [x, y, 0.0],
+            position: corner(left, top),
             color,
-            uv: [u, v],
+            uv: [uvs[0].0, uvs[0].1],
         },
         // Top right
         Vertex {
-            position: [x + w, y, 0.0],
+            position: corner(right, top),
             color,
-            uv: [u + u_width, v],
+            uv: [uvs[1].0, uvs[1].1],
         },
         // Bottom right
         Vertex {
-            position: [x + w, y + h, 0.0],
+            position: corner(right, bottom),
             color,
-            uv: [u + u_width, v + v_height],
+            uv: [uvs[2].0, uvs[2].1],
         },
         // Bottom left
         Vertex {
-            position: [x, y + h, 0.0],
+            position: corner(left, bottom),
             color,
-            uv: [u, v + v_height],
+            uv: [uvs[3].0, uvs[3].1],
         },
     ];
 
@@ -138,3 +139,87 @@ pub fn add_quad(
     mesh.vertices.extend_from_slice(&new_vertices);
     mesh.indices.extend_from_slice(&new_indices);
 }
+
+/// Add a convex polygon, triangulated as a fan around the first vertex.
+pub fn add_polygon(mesh: &mut Mesh, verts: &[(f32, f32)], color: Color) {
+    if verts.len() < 3 {
+        return;
+    }
+
+    let vertex_count: u32 = mesh.vertices.len() as u32;
+    let color: [f32; 4] = color.data();
+
+    for v in verts {
+        mesh.vertices.push(Vertex {
+            position: [v.0, v.1, 0.0],
+            color,
+            uv: [0.0, 0.0],
+        });
+    }
+
+    for i in 1..(verts.len() as u32 - 1) {
+        mesh.indices.extend_from_slice(&[vertex_count, vertex_count + i, vertex_count + i + 1]);
+    }
+}
+
+/// Add a strip of connected line segments.
+/// Multiple strips can share a mesh, since each one ends with a primitive restart.
+pub fn add_line_strip(mesh: &mut Mesh, points: &[(f32, f32)], color: Color) {
+    if points.len() < 2 {
+        return;
+    }
+
+    let vertex_count: u32 = mesh.vertices.len() as u32;
+    let color: [f32; 4] = color.data();
+
+    for (i, p) in points.iter().enumerate() {
+        mesh.vertices.push(Vertex {
+            position: [p.0, p.1, 0.0],
+            color,
+            uv: [0.0, 0.0],
+        });
+        mesh.indices.push(vertex_count + i as u32);
+    }
+
+    mesh.indices.push(PRIMITIVE_RESTART_INDEX);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::COLOR_WHITE;
+
+    fn assert_position(vertex: &Vertex, x: f32, y: f32) {
+        assert!((vertex.position[0] - x).abs() < 1e-4, "expected x {}, got {:?}", x, vertex.position);
+        assert!((vertex.position[1] - y).abs() < 1e-4, "expected y {}, got {:?}", y, vertex.position);
+    }
+
+    #[test]
+    fn add_sprite_rotates_around_origin() {
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        };
+
+        let region = SpriteRegion { x: 0, y: 0, w: 4, h: 2 };
+        add_sprite(
+            &mut mesh,
+            10.0,
+            10.0,
+            Point2f::new(2.0, 1.0),
+            Vector2f::new(1.0, 1.0),
+            std::f32::consts::FRAC_PI_2,
+            COLOR_WHITE,
+            region,
+            region.uvs(4, 2),
+        );
+
+        // A quarter turn around the center turns the 4x2 quad into a 2x4 one
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_position(&mesh.vertices[0], 11.0, 8.0);
+        assert_position(&mesh.vertices[1], 11.0, 12.0);
+        assert_position(&mesh.vertices[2], 9.0, 12.0);
+        assert_position(&mesh.vertices[3], 9.0, 8.0);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 3, 0]);
+    }
+}
@@ -7,14 +7,14 @@ use crate::{
 };
 use backend;
 use gfx_hal::{
-    adapter::{Adapter, PhysicalDevice},
+    adapter::{Adapter, DeviceType, PhysicalDevice},
     buffer,
     command::{self, BufferImageCopy, CommandBuffer},
-    device::Device,
+    device::{self, Device, OutOfMemory},
     format::{Aspects, ChannelType, Format, Swizzle},
     image::{
-        self as img, Access, Extent, Filter, Layout, Offset, SubresourceLayers, SubresourceRange,
-        ViewCapabilities, WrapMode,
+        self as img, Access, Extent, Layout, Offset, SubresourceLayers, SubresourceRange,
+        ViewCapabilities,
     },
     memory::{Barrier, Dependencies, Properties, Segment},
     pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, Subpass, SubpassDesc},
@@ -27,18 +27,20 @@ use gfx_hal::{
         Primitive,
     },
     queue::{family::QueueGroup, CommandQueue, QueueFamily, Submission},
-    window::{self, Extent2D, PresentationSurface, Surface},
-    Backend, IndexType, Instance, MemoryTypeId,
+    window::{self, AcquireError, Extent2D, PresentError, PresentationSurface, Surface},
+    Backend, IndexType, Instance, MemoryTypeId, UnsupportedBackend,
 };
 use glm;
 use std::{
     cell::RefCell,
     collections::HashMap,
-    fs::File,
-    io::{Cursor, Read},
+    io::Cursor,
     rc::Rc,
 };
 
+pub use gfx_hal::image::{Filter, WrapMode};
+pub use gfx_hal::window::PresentMode;
+
 pub(crate) type GfxInstance = ::backend::Instance;
 pub(crate) type GfxBuffer = <::backend::Backend as Backend>::Buffer;
 pub(crate) type GfxMemory = <::backend::Backend as Backend>::Memory;
@@ -66,21 +68,164 @@ pub(crate) type GfxQueueGroup = QueueGroup<backend::Backend>;
 pub(crate) type GfxDeviceHandle = Rc<RefCell<GfxDevice>>;
 pub(crate) type GpuTextureId = u16;
 
-const MAX_SPRITES: u64 = 4096;
-const MAX_BATCH_VERTICES: u64 = MAX_SPRITES * 4;
-const MAX_BATCH_INDICES: u64 = MAX_SPRITES * 6;
+// Batches start with room for this many sprites, and grow when a frame needs more
+const INITIAL_BATCH_SPRITES: usize = 64;
+const INITIAL_BATCH_VERTICES: usize = INITIAL_BATCH_SPRITES * 4;
+const INITIAL_BATCH_INDICES: usize = INITIAL_BATCH_SPRITES * 6;
 const MAX_DESCRIPTOR_SETS: usize = 512;
 
-const CLEAR_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+// Set to part of an adapter's name to use that adapter instead of the one we would pick
+const ADAPTER_OVERRIDE_ENV_VAR: &str = "GFX_ADAPTER";
+
+const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
 
 pub type RenderKey = u64;
 pub type ShaderProgramId = u16;
 pub type TextureId = u16;
 
+#[derive(Debug)]
+pub enum TextureError {
+    NotFound(TextureId),
+    // Commands using the texture have been processed into a batch that hasn't been rendered yet
+    InUse(TextureId),
+}
+
+/// Why a frame couldn't be rendered, when the caller has to do something about it.
+#[derive(Debug)]
+pub enum RenderError {
+    // The window's surface is gone, and has to be recreated with `recreate_surface` before anything can be drawn
+    SurfaceLost,
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenderError::SurfaceLost => write!(f, "The window surface was lost!"),
+        }
+    }
+}
+
+/// Why a GPU buffer couldn't be created.
+#[derive(Debug)]
+pub enum BufferError {
+    Creation(buffer::CreationError),
+    // None of the adapter's memory types that can hold the buffer have the properties it needs
+    NoSuitableMemoryType,
+    Allocation(device::AllocationError),
+    Bind(device::BindError),
+}
+
+impl std::fmt::Display for BufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BufferError::Creation(e) => write!(f, "Failed to create buffer! {:?}", e),
+            BufferError::NoSuitableMemoryType => write!(f, "Could not find an appropriate buffer memory type!"),
+            BufferError::Allocation(e) => write!(f, "Failed to allocate buffer memory! {:?}", e),
+            BufferError::Bind(e) => write!(f, "Failed to bind buffer memory! {:?}", e),
+        }
+    }
+}
+
+/// Why a render batch couldn't be created. The commands that would have gone into it aren't drawn.
+#[derive(Debug)]
+pub enum BatchError {
+    // The batch's key refers to a shader program that was never created
+    MissingShaderProgram(ShaderProgramId),
+    // The shader program's descriptor pool couldn't give the batch a descriptor set
+    DescriptorAllocation(gfx_hal::pso::AllocationError),
+    Buffer(BufferError),
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BatchError::MissingShaderProgram(id) => write!(f, "Shader program {} does not exist!", id),
+            BatchError::DescriptorAllocation(e) => write!(f, "Failed to allocate a descriptor set! {:?}", e),
+            BatchError::Buffer(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    // The embedded bytes for the named shader aren't valid SPIR-V
+    InvalidSpirv(&'static str),
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::InvalidSpirv(name) => write!(f, "Shader {} is not valid SPIR-V!", name),
+        }
+    }
+}
+
+/// Why the renderer couldn't be created. These mostly come from GPUs or drivers that the game can't run on.
+#[derive(Debug)]
+pub enum RendererError {
+    // The graphics API isn't available on this system
+    UnsupportedBackend(UnsupportedBackend),
+    SurfaceCreation(window::InitError),
+    // No adapter has a queue family that can both render and present to the window
+    NoSuitableAdapter,
+    DeviceOpen(device::CreationError),
+    // The device was opened, but didn't give back the queue group that was asked for
+    MissingQueueGroup,
+    // Ran out of memory creating the named per-frame resource
+    OutOfMemory(&'static str, OutOfMemory),
+    // Couldn't create the named buffer
+    Buffer(&'static str, BufferError),
+    Shader(ShaderError),
+}
+
+impl std::fmt::Display for RendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RendererError::UnsupportedBackend(_) => write!(f, "The graphics backend is not supported on this system!"),
+            RendererError::SurfaceCreation(e) => write!(f, "Failed to create window surface! {:?}", e),
+            RendererError::NoSuitableAdapter => write!(f, "Failed to find an adapter that can render to the window surface!"),
+            RendererError::DeviceOpen(e) => write!(f, "Failed to open the graphics device! {:?}", e),
+            RendererError::MissingQueueGroup => write!(f, "The graphics device has no queue group!"),
+            RendererError::OutOfMemory(resource, e) => write!(f, "Out of memory creating {}! {:?}", resource, e),
+            RendererError::Buffer(resource, e) => write!(f, "Failed to create {}! {}", resource, e),
+            RendererError::Shader(e) => write!(f, "Failed to create render program! {}", e),
+        }
+    }
+}
+
+impl From<ShaderError> for RendererError {
+    fn from(e: ShaderError) -> Self {
+        RendererError::Shader(e)
+    }
+}
+
+// Compiled shaders, named after their source in res/shaders/src.
+// They're embedded so that the binary doesn't depend on the working directory, and can't ship without them.
+type ShaderSource = (&'static str, &'static [u8]);
+
+const UNTEXTURED_VERT_SHADER: ShaderSource = (
+    "untextured.glslv",
+    include_bytes!("../res/shaders/bin/untextured.glslv.spv"),
+);
+const UNTEXTURED_FRAG_SHADER: ShaderSource = (
+    "untextured.glslf",
+    include_bytes!("../res/shaders/bin/untextured.glslf.spv"),
+);
+const TEXTURED_VERT_SHADER: ShaderSource = (
+    "textured.glslv",
+    include_bytes!("../res/shaders/bin/textured.glslv.spv"),
+);
+const TEXTURED_FRAG_SHADER: ShaderSource = (
+    "textured.glslf",
+    include_bytes!("../res/shaders/bin/textured.glslf.spv"),
+);
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Transparency {
     Opaque = 0,
     Transparent = 1,
+    // Adds onto what has already been drawn, for glow effects. Drawn after everything else.
+    Additive = 2,
 }
 
 impl Default for Transparency {
@@ -103,9 +248,30 @@ pub enum Renderable {
         y: f32,
         pivot: Point2f,
         scale: Vector2f,
+        rotation: f32,
         color: Color,
         region: SpriteRegion,
     },
+    // An ascii glyph from a font sheet, with its top left at x, y. Its UVs come from the batch's glyph table.
+    Glyph {
+        x: f32,
+        y: f32,
+        scale: f32,
+        color: Color,
+        ascii: u8,
+        cell_w: u32,
+        cell_h: u32,
+    },
+    // Convex polygon, with vertices in winding order
+    Polygon {
+        verts: Vec<(f32, f32)>,
+        color: Color,
+    },
+    // Connected line segments. Must be drawn with a line strip shader program.
+    LineStrip {
+        points: Vec<(f32, f32)>,
+        color: Color,
+    },
 }
 
 #[derive(Clone)]
@@ -120,6 +286,8 @@ pub struct RenderCommand {
     pub shader_program_id: ShaderProgramId,
     pub tex_id: TextureId,
     pub layer: u8,
+    // Draw order within the layer. Higher is drawn later, regardless of shader or texture.
+    pub z_order: u16,
     pub data: Renderable,
 }
 
@@ -128,6 +296,7 @@ impl RenderCommand {
         RenderBatch::gen_key(
             self.transparency,
             self.layer,
+            self.z_order,
             self.shader_program_id,
             self.tex_id,
         )
@@ -138,16 +307,38 @@ pub struct RenderBatch {
     device: GfxDeviceHandle,
     transparency: Transparency,
     layer: u8,
+    z_order: u16,
     shader_program_id: ShaderProgramId,
 
     // texture id, width, height
     tex_info: (GpuTextureId, u32, u32),
-    descriptor_set: GfxDescriptorSet,
+    descriptor_set: Option<GfxDescriptorSet>,
 
     // Buffers
     vertex_buffer: (Option<GfxBuffer>, Option<GfxMemory>, usize),
     index_buffer: (Option<GfxBuffer>, Option<GfxMemory>, usize),
     batch_mesh: Option<Mesh>,
+
+    // UVs of every ascii glyph in this batch's texture, built the first time a glyph is drawn from it.
+    // Batches are cached across frames, so text only ever computes its UVs once.
+    glyph_uvs: Option<Box<GlyphUvTable>>,
+}
+
+struct GlyphUvTable {
+    cell_w: u32,
+    cell_h: u32,
+    uvs: [[(f32, f32); 4]; 256],
+}
+
+impl GlyphUvTable {
+    fn new(cell_w: u32, cell_h: u32, sheet_w: u32, sheet_h: u32) -> Self {
+        let mut uvs = [[(0.0, 0.0); 4]; 256];
+        for (ascii, glyph_uvs) in uvs.iter_mut().enumerate() {
+            *glyph_uvs = SpriteRegion::glyph(ascii as u8, cell_w, cell_h).uvs(sheet_w, sheet_h);
+        }
+
+        GlyphUvTable { cell_w, cell_h, uvs }
+    }
 }
 
 impl RenderBatch {
@@ -155,6 +346,7 @@ impl RenderBatch {
         device: GfxDeviceHandle,
         transparency: Transparency,
         layer: u8,
+        z_order: u16,
         shader_program_id: ShaderProgramId,
         tex_info: (u16, u32, u32),
         descriptor_set: GfxDescriptorSet,
@@ -170,12 +362,14 @@ impl RenderBatch {
             device,
             transparency,
             layer,
+            z_order,
             shader_program_id,
             tex_info,
-            descriptor_set,
+            descriptor_set: Some(descriptor_set),
             vertex_buffer,
             index_buffer,
             batch_mesh,
+            glyph_uvs: None,
         }
     }
 
@@ -184,6 +378,7 @@ impl RenderBatch {
         RenderBatch::gen_key(
             self.transparency,
             self.layer,
+            self.z_order,
             self.shader_program_id,
             tex_id,
         )
@@ -194,7 +389,14 @@ impl RenderBatch {
     }
 
     pub fn descriptor_set_ref(&self) -> &GfxDescriptorSet {
-        &self.descriptor_set
+        self.descriptor_set.as_ref().unwrap()
+    }
+
+    /// Whether this batch has processed commands that haven't been rendered yet.
+    pub fn has_pending_mesh(&self) -> bool {
+        self.batch_mesh
+            .as_ref()
+            .map_or(false, |mesh| !mesh.indices.is_empty())
     }
 
     pub fn vertex_buffer_ref(&self) -> &GfxBuffer {
@@ -222,7 +424,7 @@ impl RenderBatch {
         mesh
     }
 
-    pub fn process_command(&mut self, command: RenderCommand) {
+    pub fn process_command(&mut self, command: &RenderCommand) {
         match command.data {
             Renderable::Quad {
                 bl,
@@ -238,21 +440,62 @@ impl RenderBatch {
                 y,
                 pivot,
                 scale,
+                rotation,
                 color,
                 region,
             } => {
+                let uvs = region.uvs(self.tex_info.1, self.tex_info.2);
+
                 mesh::add_sprite(
                     self.batch_mesh.as_mut().unwrap(),
                     x,
                     y,
                     pivot,
                     scale,
+                    rotation,
                     color,
                     region,
-                    self.tex_info.1,
-                    self.tex_info.2,
+                    uvs,
+                );
+            }
+            Renderable::Glyph {
+                x,
+                y,
+                scale,
+                color,
+                ascii,
+                cell_w,
+                cell_h,
+            } => {
+                // A batch only ever has one font texture, so this is built once unless the cell size changes
+                let (sheet_w, sheet_h) = (self.tex_info.1, self.tex_info.2);
+                let is_stale = match self.glyph_uvs.as_ref() {
+                    Some(table) => (table.cell_w != cell_w) || (table.cell_h != cell_h),
+                    None => true,
+                };
+                if is_stale {
+                    self.glyph_uvs = Some(Box::new(GlyphUvTable::new(cell_w, cell_h, sheet_w, sheet_h)));
+                }
+
+                let uvs = self.glyph_uvs.as_ref().unwrap().uvs[ascii as usize];
+                mesh::add_sprite(
+                    self.batch_mesh.as_mut().unwrap(),
+                    x,
+                    y,
+                    Point2f::origin(),
+                    Vector2f::new(scale, scale),
+                    0.0,
+                    color,
+                    SpriteRegion::glyph(ascii, cell_w, cell_h),
+                    uvs,
                 );
             }
+            Renderable::Polygon { ref verts, color } => {
+                mesh::add_polygon(self.batch_mesh.as_mut().unwrap(), verts, color);
+            }
+            Renderable::LineStrip { ref points, color } => {
+                mesh::add_line_strip(self.batch_mesh.as_mut().unwrap(), points, color);
+            }
         }
     }
 
@@ -262,16 +505,20 @@ impl RenderBatch {
         }
     }
 
+    // Batches are drawn in key order, so the most significant field decides first:
+    // transparency, then layer, then z order, then shader program, then texture.
     fn gen_key(
         transparency: Transparency,
         layer: u8,
+        z_order: u16,
         shader_program_id: ShaderProgramId,
         tex_id: TextureId,
     ) -> RenderKey {
         ((transparency as RenderKey) << 56)
             + ((layer as RenderKey) << 48)
-            + ((shader_program_id as RenderKey) << 32)
-            + ((tex_id as RenderKey) << 16)
+            + ((z_order as RenderKey) << 32)
+            + ((shader_program_id as RenderKey) << 16)
+            + (tex_id as RenderKey)
     }
 }
 
@@ -290,6 +537,18 @@ impl Drop for RenderBatch {
     }
 }
 
+/// Counts from processing and rendering a frame.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+    // Distinct render keys produced by process_commands, one per batch
+    pub batch_count: usize,
+    // Batches kept around for reuse, including ones that weren't drawn this frame
+    pub cached_batch_count: usize,
+    pub draw_calls: usize,
+    pub vertices: usize,
+    pub sprites: usize,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct UniformBufferObject {
     view: [[f32; 4]; 4],
@@ -304,10 +563,22 @@ pub struct GpuTexture {
     memory: Option<GfxMemory>,
     image_view: Option<GfxImageView>,
     sampler: Option<GfxSampler>,
+    filter: Filter,
+    wrap: WrapMode,
     w: u32,
     h: u32,
 }
 
+impl GpuTexture {
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn wrap(&self) -> WrapMode {
+        self.wrap
+    }
+}
+
 impl Drop for GpuTexture {
     fn drop(&mut self) {
         println!("Cleaning up GpuTexture {}", self.id);
@@ -330,6 +601,7 @@ struct RenderProgram {
     vert_shader: Option<GfxShaderModule>,
     frag_shader: Option<GfxShaderModule>,
     pipeline: Option<GfxGraphicsPipeline>,
+    additive_pipeline: Option<GfxGraphicsPipeline>,
     pipeline_layout: Option<GfxPipelineLayout>,
     descriptor_pool: Option<GfxDescriptorPool>,
     descriptor_set_layout: Option<GfxDescriptorSetLayout>,
@@ -345,6 +617,7 @@ impl Drop for RenderProgram {
             device.destroy_shader_module(self.vert_shader.take().unwrap());
             device.destroy_shader_module(self.frag_shader.take().unwrap());
             device.destroy_graphics_pipeline(self.pipeline.take().unwrap());
+            device.destroy_graphics_pipeline(self.additive_pipeline.take().unwrap());
             device.destroy_pipeline_layout(self.pipeline_layout.take().unwrap());
             device.destroy_descriptor_set_layout(self.descriptor_set_layout.take().unwrap());
 
@@ -365,8 +638,12 @@ pub struct Renderer {
     surface_color_format: Format,
     depth_format: Format,
     dimensions: Extent2D,
+    // False while the window is minimized, or the swapchain couldn't be configured
+    is_swapchain_valid: bool,
     viewport: pso::Viewport,
     render_scale: f32,
+    clear_color: [f32; 4],
+    preferred_present_mode: PresentMode,
 
     frame_semaphores: Option<Vec<GfxSemaphore>>,
     frame_fences: Option<Vec<GfxFence>>,
@@ -374,6 +651,11 @@ pub struct Renderer {
     render_pass: Option<GfxRenderPass>,
     shader_programs: HashMap<ShaderProgramId, RenderProgram>,
 
+    // Samples per pixel. Above 1, frames are drawn to the multisampled target and resolved to the surface image.
+    sample_count: img::NumSamples,
+    // Image, memory, and view of the multisampled color target. Recreated along with the swapchain.
+    msaa_target: Option<(GfxImage, GfxMemory, GfxImageView)>,
+
     uniform_buffer: Option<GfxBuffer>,
     uniform_buffer_memory: Option<GfxMemory>,
     uniform_buffer_frame_size: usize,
@@ -383,25 +665,36 @@ pub struct Renderer {
 
     frames_in_flight: usize,
     current_frame: usize,
+
+    frame_stats: RenderStats,
+    last_frame_stats: RenderStats,
 }
 
 impl Renderer {
-    pub fn new(window: &WinitWindow, render_scale: f32) -> Renderer {
+    /// `frames_in_flight` is how many frames can be recorded while earlier ones are still on the GPU.
+    /// Each one gets its own command buffer, sync objects, and slice of every vertex, index, and uniform buffer.
+    ///
+    /// `sample_count` is the number of samples per pixel for MSAA, where 1 turns it off. If the adapter doesn't
+    /// support the count, the highest supported count below it is used instead.
+    pub fn new(
+        window: &WinitWindow,
+        render_scale: f32,
+        frames_in_flight: usize,
+        sample_count: img::NumSamples,
+    ) -> Result<Renderer, RendererError> {
+        assert!(frames_in_flight > 0, "Renderer needs at least one frame in flight!");
+
         // Create an instance, which is the entry point to the graphics API.
-        let instance =
-            GfxInstance::create("gfx-rs", 1).expect("Failed to create backend instance!");
+        let instance = GfxInstance::create("gfx-rs", 1).map_err(RendererError::UnsupportedBackend)?;
 
         // Create a surface, which is an abstraction over the OS's native window.
-        let surface = unsafe {
-            instance
-                .create_surface(window)
-                .expect("Failed to create window surface!")
-        };
+        let surface = unsafe { instance.create_surface(window) }.map_err(RendererError::SurfaceCreation)?;
 
-        // Grab the first available adapter.
         // An adapter represents a physical device, like a GPU.
-        // TODO do we actually need to iterate and grab a proper adapter
-        let adapter = instance.enumerate_adapters().remove(0);
+        let adapter = select_adapter(instance.enumerate_adapters(), &surface)?;
+        println!("Using adapter: {} ({:?})", adapter.info.name, adapter.info.device_type);
+
+        let sample_count = select_sample_count(&adapter.physical_device, sample_count);
 
         let family = adapter
             .queue_families
@@ -409,22 +702,20 @@ impl Renderer {
             .find(|family| {
                 surface.supports_queue_family(family) && family.queue_type().supports_graphics()
             })
-            .unwrap();
+            .ok_or(RendererError::NoSuitableAdapter)?;
 
         let mut gpu = unsafe {
             adapter
                 .physical_device
                 .open(&[(family, &[1.0])], gfx_hal::Features::empty())
-                .unwrap()
+                .map_err(RendererError::DeviceOpen)?
         };
 
         // The device is a logical device that allows us to perform GPU operations.
         // The queue group contains a set of command queues which we can submit drawing commands to.
-        let queue_group = gpu.queue_groups.pop().unwrap();
+        let queue_group = gpu.queue_groups.pop().ok_or(RendererError::MissingQueueGroup)?;
         let device = gpu.device;
 
-        let frames_in_flight = 2;
-
         // The number of the rest of the resources is based on the frames in flight.
         let mut frame_semaphores: Vec<GfxSemaphore> = Vec::with_capacity(frames_in_flight);
         let mut frame_fences: Vec<GfxFence> = Vec::with_capacity(frames_in_flight);
@@ -441,7 +732,7 @@ impl Renderer {
                             queue_group.family,
                             pool::CommandPoolCreateFlags::empty(),
                         )
-                        .expect("Failed to create create command pool for frame!"),
+                        .map_err(|e| RendererError::OutOfMemory("frame command pool", e))?,
                 );
             }
         }
@@ -450,13 +741,13 @@ impl Renderer {
             frame_semaphores.push(
                 device
                     .create_semaphore()
-                    .expect("Failed to create frame semaphore!"),
+                    .map_err(|e| RendererError::OutOfMemory("frame semaphore", e))?,
             );
 
             frame_fences.push(
                 device
                     .create_fence(true)
-                    .expect("Failed to create frame fence!"),
+                    .map_err(|e| RendererError::OutOfMemory("frame fence", e))?,
             );
 
             command_buffers.push(unsafe { command_pools[i].allocate_one(command::Level::Primary) });
@@ -480,7 +771,14 @@ impl Renderer {
         // Wrapping the device in a reference counted ref cell, because it will need to be shared with various resources
         let device: GfxDeviceHandle = Rc::new(RefCell::new(device));
 
-        let render_pass = create_render_pass(device.clone(), surface_color_format, depth_format);
+        let render_pass = create_render_pass(
+            device.clone(),
+            surface_color_format,
+            depth_format,
+            Layout::Present,
+            sample_count,
+        )
+        .map_err(|e| RendererError::OutOfMemory("render pass", e))?;
         let shader_programs = {
             let mut shader_programs: HashMap<u16, RenderProgram> = HashMap::new();
 
@@ -489,19 +787,20 @@ impl Renderer {
                 create_render_program(
                     device.clone(),
                     &render_pass,
-                    "gfx-lib/res/shaders/bin/untextured.glslv.spv",
-                    "gfx-lib/res/shaders/bin/untextured.glslf.spv",
+                    sample_count,
+                    UNTEXTURED_VERT_SHADER,
+                    UNTEXTURED_FRAG_SHADER,
                     vec![ShaderDescriptorBinding {
                         ty: DescriptorType::Buffer {
                             ty: BufferDescriptorType::Uniform,
                             format: BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         stage_flags: ShaderStageFlags::VERTEX,
                     }],
                     Primitive::TriangleList
-                ),
+                )?,
             );
 
             shader_programs.insert(
@@ -509,14 +808,15 @@ impl Renderer {
                 create_render_program(
                     device.clone(),
                     &render_pass,
-                    "gfx-lib/res/shaders/bin/textured.glslv.spv",
-                    "gfx-lib/res/shaders/bin/textured.glslf.spv",
+                    sample_count,
+                    TEXTURED_VERT_SHADER,
+                    TEXTURED_FRAG_SHADER,
                     vec![
                         ShaderDescriptorBinding {
                             ty: DescriptorType::Buffer {
                                 ty: BufferDescriptorType::Uniform,
                                 format: BufferDescriptorFormat::Structured {
-                                    dynamic_offset: false,
+                                    dynamic_offset: true,
                                 },
                             },
                             stage_flags: ShaderStageFlags::VERTEX,
@@ -535,7 +835,7 @@ impl Renderer {
                         },
                     ],
                     Primitive::TriangleList,
-                ),
+                )?,
             );
 
             shader_programs.insert(
@@ -543,19 +843,20 @@ impl Renderer {
                 create_render_program(
                     device.clone(),
                     &render_pass,
-                    "gfx-lib/res/shaders/bin/untextured.glslv.spv",
-                    "gfx-lib/res/shaders/bin/untextured.glslf.spv",
+                    sample_count,
+                    UNTEXTURED_VERT_SHADER,
+                    UNTEXTURED_FRAG_SHADER,
                     vec![ShaderDescriptorBinding {
                         ty: DescriptorType::Buffer {
                             ty: BufferDescriptorType::Uniform,
                             format: BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         stage_flags: ShaderStageFlags::VERTEX,
                     }],
                     Primitive::LineStrip
-                ),
+                )?,
             );
 
             shader_programs
@@ -572,7 +873,8 @@ impl Renderer {
                     projection: glm::Mat4::identity().into(),
                 },
                 frames_in_flight,
-            );
+            )
+            .map_err(|e| RendererError::Buffer("uniform buffer", e))?;
 
         let window_inner_size = window.inner_size();
         let dimensions = Extent2D {
@@ -590,7 +892,7 @@ impl Renderer {
             depth: 0.0..1.0,
         };
 
-        Renderer {
+        Ok(Renderer {
             instance,
             surface: Some(surface),
             adapter,
@@ -601,12 +903,15 @@ impl Renderer {
             surface_color_format,
             depth_format,
             dimensions,
+            is_swapchain_valid: false,
             viewport,
             render_scale,
             frame_semaphores: Some(frame_semaphores),
             frame_fences: Some(frame_fences),
             render_pass: Some(render_pass),
             shader_programs,
+            sample_count,
+            msaa_target: None,
             uniform_buffer: Some(uniform_buffer),
             uniform_buffer_memory: Some(uniform_buffer_memory),
             uniform_buffer_frame_size,
@@ -614,7 +919,28 @@ impl Renderer {
             batches: HashMap::new(),
             frames_in_flight,
             current_frame: 0,
-        }
+            clear_color: DEFAULT_CLEAR_COLOR,
+            preferred_present_mode: PresentMode::MAILBOX,
+            frame_stats: RenderStats::default(),
+            last_frame_stats: RenderStats::default(),
+        })
+    }
+
+    /// Set the color the screen is cleared to at the start of each frame.
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = color.data();
+    }
+
+    /// Set the present mode to use when the surface supports it. FIFO waits for vsync, MAILBOX
+    /// replaces the queued frame instead of waiting, and IMMEDIATE presents right away and may tear.
+    /// Takes effect the next time the swapchain is rebuilt.
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        self.preferred_present_mode = mode;
+    }
+
+    /// Stats from the last frame that was rendered.
+    pub fn last_frame_stats(&self) -> RenderStats {
+        self.last_frame_stats
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
@@ -623,27 +949,75 @@ impl Renderer {
         self.rebuild_swapchain();
     }
 
+    /// Create batches ahead of time, so the first frame that draws with each of them doesn't have to stop and
+    /// allocate its buffers and descriptor set. Each entry is `(transparency, layer, z_order, shader_program_id, tex_id)`,
+    /// the same fields that make up a `RenderCommand`'s key, and batches that already exist are left alone.
+    ///
+    /// To find the combinations worth listing, look for draws that don't happen on the first frame (tooltips,
+    /// overlays, effects that only show up later) and write down what is bound when they're drawn. A key that is
+    /// never used only costs the memory of an empty batch. Call this after the textures are created, since a
+    /// batch looks up its texture's size when it's created.
+    pub fn prewarm_batches(&mut self, keys: &[(Transparency, u8, u16, ShaderProgramId, TextureId)]) {
+        for &(transparency, layer, z_order, shader_program_id, tex_id) in keys {
+            let key = RenderBatch::gen_key(transparency, layer, z_order, shader_program_id, tex_id);
+            if self.batches.contains_key(&key) {
+                continue;
+            }
+
+            if let Err(e) = self.create_render_batch(transparency, layer, z_order, shader_program_id, tex_id) {
+                eprintln!("[GFX] Failed to prewarm render batch {}! {}", key, e);
+            }
+        }
+    }
+
     pub fn create_render_batch(
         &mut self,
         transparency: Transparency,
         layer: u8,
+        z_order: u16,
         shader_program_id: ShaderProgramId,
         tex_id: u16,
-    ) -> Result<RenderKey, gfx_hal::pso::AllocationError> {
+    ) -> Result<RenderKey, BatchError> {
         // If we already have a batch with this key, get it
-        let key = RenderBatch::gen_key(transparency, layer, shader_program_id, tex_id);
+        let key = RenderBatch::gen_key(transparency, layer, z_order, shader_program_id, tex_id);
         if let Some(batch) = self.batches.get_mut(&key) {
             batch.clear();
             return Ok(key);
         }
 
+        if !self.shader_programs.contains_key(&shader_program_id) {
+            return Err(BatchError::MissingShaderProgram(shader_program_id));
+        }
+
+        // The buffers are made before the descriptor set, since they're simpler to clean up if something after them fails
+
+        // Create vertex buffer
+        let (vertex_buffer, vertex_buffer_memory, vertex_buffer_frame_size) = create_vertex_buffer(
+            self.device.clone(),
+            &self.adapter.physical_device,
+            &[],
+            INITIAL_BATCH_VERTICES,
+            self.frames_in_flight,
+        )
+        .map_err(BatchError::Buffer)?;
+
+        // Create index buffer
+        let (index_buffer, index_buffer_memory, index_buffer_frame_size) = match create_index_buffer(
+            self.device.clone(),
+            &self.adapter.physical_device,
+            &[],
+            INITIAL_BATCH_INDICES,
+            self.frames_in_flight,
+        ) {
+            Ok(index_buffer) => index_buffer,
+            Err(e) => {
+                destroy_buffer(&self.device, vertex_buffer, vertex_buffer_memory);
+                return Err(BatchError::Buffer(e));
+            }
+        };
+
         let (descriptor_set, shader_descriptor_bindings) = {
-            let shader_program = match self.shader_programs.get_mut(&shader_program_id) {
-                Some(s) => s,
-                None => panic!(
-                    "Failed to create render batch: Referenced shader program did not exist!"
-                ),
-            };
+            let shader_program = self.shader_programs.get_mut(&shader_program_id).unwrap();
 
             // Grab the descriptor pool and layout from the shader program
             let pool: &mut GfxDescriptorPool = shader_program.descriptor_pool.as_mut().unwrap();
@@ -654,8 +1028,9 @@ impl Renderer {
             let descriptor_set = match unsafe { pool.allocate_set(layout) } {
                 Ok(set) => set,
                 Err(e) => {
-                    eprintln!("Failed to create batch! {:?}", e);
-                    panic!();
+                    destroy_buffer(&self.device, vertex_buffer, vertex_buffer_memory);
+                    destroy_buffer(&self.device, index_buffer, index_buffer_memory);
+                    return Err(BatchError::DescriptorAllocation(e));
                 }
             };
 
@@ -665,22 +1040,6 @@ impl Renderer {
             )
         };
 
-        // Create vertex buffer
-        let (vertex_buffer, vertex_buffer_memory, vertex_buffer_frame_size) = create_vertex_buffer(
-            self.device.clone(),
-            &self.adapter.physical_device,
-            &[],
-            self.frames_in_flight,
-        );
-
-        // Create index buffer
-        let (index_buffer, index_buffer_memory, index_buffer_frame_size) = create_index_buffer(
-            self.device.clone(),
-            &self.adapter.physical_device,
-            &[],
-            self.frames_in_flight,
-        );
-
         let tex_info = if let Some(tex) = self.textures.get(&tex_id) {
             (tex_id, tex.w, tex.h)
         } else {
@@ -691,6 +1050,7 @@ impl Renderer {
             self.device.clone(),
             transparency,
             layer,
+            z_order,
             shader_program_id,
             tex_info,
             descriptor_set,
@@ -745,7 +1105,8 @@ impl Renderer {
                             set,
                             binding: i as u32,
                             array_offset: 0,
-                            descriptors: Some(Descriptor::Buffer(self.uniform_buffer.as_ref().unwrap(), buffer::SubRange { offset: 0, size: None })),
+                            // Bound to one frame's slice, and offset to the current frame's when drawing
+                            descriptors: Some(Descriptor::Buffer(self.uniform_buffer.as_ref().unwrap(), buffer::SubRange { offset: 0, size: Some(self.uniform_buffer_frame_size as u64) })),
                         });
                     }
                     DescriptorType::Image { ty: ImageDescriptorType::Sampled { .. }, .. } => {
@@ -787,95 +1148,100 @@ impl Renderer {
     }
 
     /// Process some `RenderCommand`s, sorting them and producing batches that can be rendered.
-    pub fn process_commands(&mut self, mut commands: Vec<RenderCommand>) -> Vec<RenderKey> {
-        commands.sort_by(|a, b| a.key().cmp(&b.key()));
-
-        // Process commands into batches
+    pub fn process_commands<'a>(
+        &mut self,
+        commands: impl IntoIterator<Item = &'a RenderCommand>,
+    ) -> Vec<RenderKey> {
         let mut batch_keys: Vec<RenderKey> = Vec::new();
-        let mut batch: Option<&mut RenderBatch> = None;
-
-        for command in commands {
-            let cmd_transparency = command.transparency;
-            let cmd_layer = command.layer;
-            let cmd_tex_id = command.tex_id;
-            let cmd_shader_program_id = command.shader_program_id;
-
-            // Flush the current batch if we are encountering new data
-            if batch.is_some() {
-                let (
-                    batch_transparency,
-                    batch_layer,
-                    batch_shader_program_id,
-                    batch_tex_id,
-                    batch_key,
-                ) = {
-                    let b = batch.as_ref().unwrap();
-                    (
-                        b.transparency,
-                        b.layer,
-                        b.shader_program_id,
-                        b.tex_id(),
-                        b.key(),
-                    )
-                };
 
-                if (batch_transparency != cmd_transparency)
-                    || (batch_layer != cmd_layer)
-                    || (batch_shader_program_id != cmd_shader_program_id)
-                    || (batch_tex_id != cmd_tex_id)
-                {
-                    batch_keys.push(batch_key);
-                    batch = None;
-                }
+        for (key, commands) in group_commands(commands) {
+            let first = &commands[0];
+            if let Err(e) = self.create_render_batch(
+                first.transparency,
+                first.layer,
+                first.z_order,
+                first.shader_program_id,
+                first.tex_id,
+            ) {
+                // Skip just this group, so one bad batch doesn't take the rest of the frame with it
+                eprintln!("[GFX] Failed to create render batch {}! {}", key, e);
+                continue;
             }
 
-            // Begin a new batch if needed
-            if batch.is_none() {
-                let key = self
-                    .create_render_batch(
-                        cmd_transparency,
-                        cmd_layer,
-                        cmd_shader_program_id,
-                        cmd_tex_id,
-                    )
-                    .unwrap();
-                batch = Some(self.batches.get_mut(&key).unwrap());
-            }
+            let batch = self.batches.get_mut(&key).unwrap();
+            for command in commands {
+                match command.data {
+                    Renderable::Sprite { .. } | Renderable::Glyph { .. } => self.frame_stats.sprites += 1,
+                    _ => {}
+                }
 
-            if let Some(batch) = batch.as_mut() {
                 batch.process_command(command);
             }
-        }
 
-        // Flush the current batch since we are done with all commands
-        if batch.is_some() {
-            batch_keys.push(batch.unwrap().key());
+            batch_keys.push(key);
         }
 
+        self.frame_stats.batch_count = batch_keys.len();
+        self.frame_stats.cached_batch_count = self.batches.len();
+
         batch_keys
     }
 
-    pub fn render(&mut self, scale_factor: f32, batch_keys: Vec<RenderKey>) {
+    /// Draw the batches to the window. Returns `RenderError::SurfaceLost` if the window's surface is gone,
+    /// in which case nothing more can be drawn until `recreate_surface` is called.
+    pub fn render(&mut self, scale_factor: f32, batch_keys: Vec<RenderKey>) -> Result<(), RenderError> {
         if self.surface.is_none() {
             panic!("Failed to render: Renderer surface was None!");
         }
 
+        // Nothing to draw to while minimized. Keep trying in case the swapchain failed for another reason.
+        if !self.is_swapchain_valid {
+            self.rebuild_swapchain();
+            if !self.is_swapchain_valid {
+                self.discard_frame(&batch_keys);
+                return Ok(());
+            }
+        }
+
         let surface_image = unsafe {
             match self.surface.as_mut().unwrap().acquire_image(!0) {
                 Ok((image, _)) => image,
-                Err(_) => {
+                Err(AcquireError::NotReady) | Err(AcquireError::Timeout) => {
+                    self.discard_frame(&batch_keys);
+                    return Ok(());
+                }
+                Err(AcquireError::OutOfDate) => {
+                    self.discard_frame(&batch_keys);
                     self.rebuild_swapchain();
-                    return;
+                    return Ok(());
+                }
+                Err(AcquireError::SurfaceLost(_)) => {
+                    eprintln!("[Renderer] Surface lost while acquiring an image.");
+                    self.discard_frame(&batch_keys);
+                    return Err(RenderError::SurfaceLost);
+                }
+                Err(AcquireError::DeviceLost(_)) => {
+                    panic!("[Renderer] GPU device lost while acquiring an image! The driver may have crashed or been updated.");
+                }
+                Err(AcquireError::OutOfMemory(e)) => {
+                    panic!("[Renderer] Out of memory while acquiring an image! {:?}", e);
                 }
             }
         };
 
         let framebuffer = unsafe {
             use std::borrow::Borrow;
+
+            // With MSAA, the frame is drawn to the multisampled target and resolved into the surface image
+            let attachments: Vec<&GfxImageView> = match self.msaa_target.as_ref() {
+                Some((_, _, msaa_view)) => vec![msaa_view, surface_image.borrow()],
+                None => vec![surface_image.borrow()],
+            };
+
             RefCell::borrow(&self.device)
                 .create_framebuffer(
                     self.render_pass.as_ref().unwrap(),
-                    std::iter::once(surface_image.borrow()),
+                    attachments,
                     Extent {
                         width: self.dimensions.width,
                         height: self.dimensions.height,
@@ -886,42 +1252,8 @@ impl Renderer {
         };
 
         let frame_idx = self.current_frame % self.frames_in_flight;
-
-        unsafe {
-            let fence = &self.frame_fences.as_ref().unwrap()[frame_idx];
-            self.device
-                .borrow()
-                .wait_for_fence(fence, !0)
-                .expect("Failed to wait for frame fence!");
-            self.device
-                .borrow()
-                .reset_fence(fence)
-                .expect("Failed to reset frame fence!");
-            self.command_pools.as_mut().unwrap()[frame_idx].reset(false);
-        }
-
-        let projection = glm::ortho(
-            0.0,
-            (self.dimensions.width as f32 / scale_factor) / self.render_scale,
-            0.0,
-            (self.dimensions.height as f32 / scale_factor) / self.render_scale,
-            -1.0,
-            100.0,
-        );
-
-        let ubo = UniformBufferObject {
-            view: glm::Mat4::identity().into(),
-            model: glm::Mat4::identity().into(),
-            projection: projection.into(),
-        };
-
-        update_buffer(
-            self.uniform_buffer_memory.as_ref().unwrap(),
-            frame_idx,
-            self.uniform_buffer_frame_size,
-            self.device.clone(),
-            &[ubo],
-        );
+        self.wait_for_frame(frame_idx);
+        self.update_uniform_buffer(scale_factor, frame_idx);
 
         let final_command_buffer = unsafe {
             let command_buffer = &mut self.command_buffers[frame_idx];
@@ -936,16 +1268,13 @@ impl Renderer {
                 self.viewport.rect,
                 &[command::ClearValue {
                     color: command::ClearColor {
-                        float32: CLEAR_COLOR,
+                        float32: self.clear_color,
                     },
                 }],
                 command::SubpassContents::Inline,
             );
 
-            // Record rendering of batches into command buffer
-            for batch_key in batch_keys {
-                self.render_batch(batch_key, frame_idx);
-            }
+            self.render_batches(batch_keys, frame_idx);
 
             let command_buffer = &mut self.command_buffers[frame_idx];
             command_buffer.end_render_pass();
@@ -978,57 +1307,431 @@ impl Renderer {
             self.device.borrow().destroy_framebuffer(framebuffer);
         }
 
-        if result.is_err() {
-            self.rebuild_swapchain();
+        // The frame was submitted either way, so finish it before reporting a lost surface
+        let mut surface_lost = false;
+        match result {
+            Ok(_) => {}
+            Err(PresentError::OutOfDate) => {
+                self.rebuild_swapchain();
+            }
+            Err(PresentError::SurfaceLost(_)) => {
+                eprintln!("[Renderer] Surface lost while presenting.");
+                surface_lost = true;
+            }
+            Err(PresentError::DeviceLost(_)) => {
+                panic!("[Renderer] GPU device lost while presenting! The driver may have crashed or been updated.");
+            }
+            Err(PresentError::OutOfMemory(e)) => {
+                panic!("[Renderer] Out of memory while presenting! {:?}", e);
+            }
         }
 
+        self.last_frame_stats = std::mem::take(&mut self.frame_stats);
         self.current_frame += 1;
+
+        if surface_lost {
+            Err(RenderError::SurfaceLost)
+        } else {
+            Ok(())
+        }
     }
 
-    fn render_batch(&mut self, batch_key: RenderKey, frame_idx: usize) {
-        let command_buffer = &mut self.command_buffers[frame_idx];
+    /// Replace a lost surface with a new one for the window, and rebuild the swapchain for it.
+    pub fn recreate_surface(&mut self, window: &WinitWindow) -> Result<(), RendererError> {
+        // Frames still in flight may be presenting to the old surface
+        self.device
+            .borrow()
+            .wait_idle()
+            .expect("Failed to wait for device idle!");
 
-        let batch = self.batches.get_mut(&batch_key).unwrap();
-        let mesh = batch.take_mesh();
-        let indices_len = mesh.indices.len() as u32;
+        if let Some(mut surface) = self.surface.take() {
+            unsafe {
+                surface.unconfigure_swapchain(&self.device.borrow());
+                self.instance.destroy_surface(surface);
+            }
+        }
+        self.is_swapchain_valid = false;
 
-        update_buffer(
-            batch.vertex_buffer_mem_ref(),
-            frame_idx,
-            batch.vertex_buffer.2,
-            self.device.clone(),
-            &mesh.vertices,
-        );
-        update_buffer(
-            batch.index_buffer_mem_ref(),
-            frame_idx,
-            batch.index_buffer.2,
-            self.device.clone(),
-            &mesh.indices,
-        );
+        let surface = unsafe { self.instance.create_surface(window) }.map_err(RendererError::SurfaceCreation)?;
+        self.surface = Some(surface);
+        println!("Recreated the window surface.");
 
-        unsafe {
-            let shader_program = match self.shader_programs.get(&batch.shader_program_id) {
-                Some(s) => s,
-                None => panic!("Failed to render batch: Referenced shader program did not exist!"),
-            };
+        self.rebuild_swapchain();
+        Ok(())
+    }
 
-            command_buffer.bind_graphics_pipeline(shader_program.pipeline.as_ref().unwrap());
+    /// Render the batches into an offscreen image instead of the window, and read it back as RGBA.
+    /// The frame isn't presented, so call this in place of `render` for the frame being captured.
+    /// Returns None if there's nothing to capture, like while minimized, or the surface format isn't supported.
+    pub fn capture_frame(&mut self, scale_factor: f32, batch_keys: Vec<RenderKey>) -> Option<image::RgbaImage> {
+        if !self.is_swapchain_valid {
+            self.discard_frame(&batch_keys);
+            return None;
+        }
 
-            // Bind buffers
-            let vertex_buffer_offset = (frame_idx * batch.vertex_buffer.2) as u64;
-            command_buffer.bind_vertex_buffers(
-                0,
-                Some((
-                    batch.vertex_buffer_ref(),
-                    buffer::SubRange {
-                        offset: vertex_buffer_offset,
-                        size: Some(batch.vertex_buffer.2 as u64),
-                    },
-                )),
-            );
+        // The offscreen image uses the surface format so the pipelines can draw to it, and gets converted to RGBA after
+        let is_bgra = match self.surface_color_format {
+            Format::Bgra8Srgb | Format::Bgra8Unorm => true,
+            Format::Rgba8Srgb | Format::Rgba8Unorm => false,
+            format => {
+                eprintln!("[Renderer] Can't capture a frame with surface format {:?}!", format);
+                self.discard_frame(&batch_keys);
+                return None;
+            }
+        };
 
-            let index_buffer_offset = (frame_idx * batch.index_buffer.2) as u64;
+        let width = self.dimensions.width;
+        let height = self.dimensions.height;
+
+        let (capture_image, capture_memory, capture_view) = create_image(
+            self.device.clone(),
+            &self.adapter.physical_device,
+            width,
+            height,
+            self.surface_color_format,
+            img::Usage::COLOR_ATTACHMENT | img::Usage::TRANSFER_SRC,
+            Aspects::COLOR,
+            1,
+        );
+
+        // Rows in the readback buffer have the same alignment requirements as texture uploads
+        let row_alignment_mask = self
+            .adapter
+            .physical_device
+            .limits()
+            .optimal_buffer_copy_pitch_alignment as u32
+            - 1;
+        let image_stride: usize = 4;
+        let row_pitch = (width * image_stride as u32 + row_alignment_mask) & !row_alignment_mask;
+        let readback_size = (height * row_pitch) as usize;
+
+        let (readback_buffer, readback_memory) = match create_buffer(
+            self.device.clone(),
+            &self.adapter.physical_device,
+            buffer::Usage::TRANSFER_DST,
+            Properties::CPU_VISIBLE,
+            readback_size,
+        ) {
+            Ok(readback) => readback,
+            Err(e) => {
+                eprintln!("[Renderer] Failed to create the capture readback buffer! {}", e);
+                unsafe {
+                    let device = self.device.borrow();
+                    device.destroy_image_view(capture_view);
+                    device.destroy_image(capture_image);
+                    device.free_memory(capture_memory);
+                }
+                self.discard_frame(&batch_keys);
+                return None;
+            }
+        };
+
+        // Same as the main render pass, but leaves the image ready to copy from instead of present.
+        // The pipelines only work with render passes that have their sample count, so this resolves the same way.
+        let render_pass = match create_render_pass(
+            self.device.clone(),
+            self.surface_color_format,
+            self.depth_format,
+            Layout::TransferSrcOptimal,
+            self.sample_count,
+        ) {
+            Ok(render_pass) => render_pass,
+            Err(e) => {
+                eprintln!("[Renderer] Failed to create the capture render pass! {:?}", e);
+                unsafe {
+                    let device = self.device.borrow();
+                    device.destroy_image_view(capture_view);
+                    device.destroy_image(capture_image);
+                    device.free_memory(capture_memory);
+                    device.destroy_buffer(readback_buffer);
+                    device.free_memory(readback_memory);
+                }
+                self.discard_frame(&batch_keys);
+                return None;
+            }
+        };
+
+        let attachments: Vec<&GfxImageView> = match self.msaa_target.as_ref() {
+            Some((_, _, msaa_view)) => vec![msaa_view, &capture_view],
+            None => vec![&capture_view],
+        };
+
+        let framebuffer = unsafe {
+            self.device
+                .borrow()
+                .create_framebuffer(
+                    &render_pass,
+                    attachments,
+                    Extent {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                )
+                .unwrap()
+        };
+
+        let frame_idx = self.current_frame % self.frames_in_flight;
+        self.wait_for_frame(frame_idx);
+        self.update_uniform_buffer(scale_factor, frame_idx);
+
+        unsafe {
+            let command_buffer = &mut self.command_buffers[frame_idx];
+
+            command_buffer.begin_primary(command::CommandBufferFlags::ONE_TIME_SUBMIT);
+            command_buffer.set_viewports(0, &[self.viewport.clone()]);
+            command_buffer.set_scissors(0, &[self.viewport.rect]);
+
+            command_buffer.begin_render_pass(
+                &render_pass,
+                &framebuffer,
+                self.viewport.rect,
+                &[command::ClearValue {
+                    color: command::ClearColor {
+                        float32: self.clear_color,
+                    },
+                }],
+                command::SubpassContents::Inline,
+            );
+
+            self.render_batches(batch_keys, frame_idx);
+
+            let command_buffer = &mut self.command_buffers[frame_idx];
+            command_buffer.end_render_pass();
+
+            let image_barrier = Barrier::Image {
+                states: (Access::COLOR_ATTACHMENT_WRITE, Layout::TransferSrcOptimal)
+                    ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                target: &capture_image,
+                families: None,
+                range: SubresourceRange {
+                    aspects: Aspects::COLOR,
+                    levels: 0..1,
+                    layers: 0..1,
+                },
+            };
+
+            command_buffer.pipeline_barrier(
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[image_barrier],
+            );
+
+            command_buffer.copy_image_to_buffer(
+                &capture_image,
+                Layout::TransferSrcOptimal,
+                &readback_buffer,
+                &[BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: row_pitch / (image_stride as u32),
+                    buffer_height: height,
+                    image_layers: SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0..1,
+                    },
+                    image_offset: Offset { x: 0, y: 0, z: 0 },
+                    image_extent: Extent {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                }],
+            );
+
+            let buffer_barrier = Barrier::Buffer {
+                states: buffer::Access::TRANSFER_WRITE..buffer::Access::HOST_READ,
+                target: &readback_buffer,
+                families: None,
+                range: buffer::SubRange::WHOLE,
+            };
+
+            command_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::HOST,
+                Dependencies::empty(),
+                &[buffer_barrier],
+            );
+
+            command_buffer.finish();
+
+            self.queue_group.queues[0].submit_without_semaphores(
+                std::iter::once(&self.command_buffers[frame_idx]),
+                Some(&mut self.frame_fences.as_mut().unwrap()[frame_idx]),
+            );
+
+            self.device
+                .borrow()
+                .wait_for_fence(&self.frame_fences.as_ref().unwrap()[frame_idx], !0)
+                .expect("Failed to wait for capture fence!");
+        }
+
+        // Copy the rows out, dropping the row padding and converting to RGBA
+        let mut pixels: Vec<u8> = Vec::with_capacity((width * height) as usize * image_stride);
+        unsafe {
+            let device = self.device.borrow();
+            let mapping = device
+                .map_memory(&readback_memory, Segment::ALL)
+                .expect("Failed to map readback memory!");
+            device
+                .invalidate_mapped_memory_ranges(std::iter::once((&readback_memory, Segment::ALL)))
+                .unwrap();
+
+            for y in 0..height {
+                let row = std::slice::from_raw_parts(
+                    mapping.offset((y * row_pitch) as isize),
+                    width as usize * image_stride,
+                );
+
+                for pixel in row.chunks_exact(image_stride) {
+                    if is_bgra {
+                        pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+                    } else {
+                        pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+                    }
+                }
+            }
+
+            device.unmap_memory(&readback_memory);
+        }
+
+        unsafe {
+            let device = self.device.borrow();
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            device.destroy_image_view(capture_view);
+            device.destroy_image(capture_image);
+            device.free_memory(capture_memory);
+            device.destroy_buffer(readback_buffer);
+            device.free_memory(readback_memory);
+        }
+
+        self.last_frame_stats = std::mem::take(&mut self.frame_stats);
+        self.current_frame += 1;
+
+        image::RgbaImage::from_raw(width, height, pixels)
+    }
+
+    /// Wait until the GPU is done with a frame's resources, so they can be reused.
+    fn wait_for_frame(&mut self, frame_idx: usize) {
+        unsafe {
+            let fence = &self.frame_fences.as_ref().unwrap()[frame_idx];
+            self.device
+                .borrow()
+                .wait_for_fence(fence, !0)
+                .expect("Failed to wait for frame fence!");
+            self.device
+                .borrow()
+                .reset_fence(fence)
+                .expect("Failed to reset frame fence!");
+            self.command_pools.as_mut().unwrap()[frame_idx].reset(false);
+        }
+    }
+
+    fn update_uniform_buffer(&mut self, scale_factor: f32, frame_idx: usize) {
+        let projection = glm::ortho(
+            0.0,
+            (self.dimensions.width as f32 / scale_factor) / self.render_scale,
+            0.0,
+            (self.dimensions.height as f32 / scale_factor) / self.render_scale,
+            -1.0,
+            100.0,
+        );
+
+        let ubo = UniformBufferObject {
+            view: glm::Mat4::identity().into(),
+            model: glm::Mat4::identity().into(),
+            projection: projection.into(),
+        };
+
+        update_buffer(
+            self.uniform_buffer_memory.as_ref().unwrap(),
+            frame_idx,
+            self.uniform_buffer_frame_size,
+            self.device.clone(),
+            &[ubo],
+        );
+    }
+
+    /// Record rendering of batches into the frame's command buffer, which must be in a render pass.
+    fn render_batches(&mut self, mut batch_keys: Vec<RenderKey>, frame_idx: usize) {
+        // Draw in key order no matter how the keys were gathered, so transparency always draws last
+        batch_keys.sort();
+        batch_keys.dedup();
+
+        for batch_key in batch_keys {
+            self.render_batch(batch_key, frame_idx);
+        }
+    }
+
+    /// Throw away a frame's batched geometry without drawing it, so it doesn't pile up into the next frame.
+    fn discard_frame(&mut self, batch_keys: &[RenderKey]) {
+        for key in batch_keys {
+            if let Some(batch) = self.batches.get_mut(key) {
+                batch.clear();
+            }
+        }
+
+        self.frame_stats = RenderStats::default();
+    }
+
+    fn render_batch(&mut self, batch_key: RenderKey, frame_idx: usize) {
+        let uniform_buffer_offset = (frame_idx * self.uniform_buffer_frame_size) as u32;
+
+        let mesh = self.batches.get_mut(&batch_key).unwrap().take_mesh();
+        if let Err(e) = self.reserve_batch_buffers(batch_key, mesh.vertices.len(), mesh.indices.len()) {
+            // The mesh doesn't fit in the buffers it has, so the batch can't be drawn this frame
+            eprintln!("[GFX] Failed to grow render batch {}! {}", batch_key, e);
+            return;
+        }
+
+        let command_buffer = &mut self.command_buffers[frame_idx];
+        let batch = self.batches.get_mut(&batch_key).unwrap();
+        let indices_len = mesh.indices.len() as u32;
+
+        self.frame_stats.draw_calls += 1;
+        self.frame_stats.vertices += mesh.vertices.len();
+
+        update_buffer(
+            batch.vertex_buffer_mem_ref(),
+            frame_idx,
+            batch.vertex_buffer.2,
+            self.device.clone(),
+            &mesh.vertices,
+        );
+        update_buffer(
+            batch.index_buffer_mem_ref(),
+            frame_idx,
+            batch.index_buffer.2,
+            self.device.clone(),
+            &mesh.indices,
+        );
+
+        unsafe {
+            let shader_program = match self.shader_programs.get(&batch.shader_program_id) {
+                Some(s) => s,
+                None => panic!("Failed to render batch: Referenced shader program did not exist!"),
+            };
+
+            let pipeline = match batch.transparency {
+                Transparency::Additive => shader_program.additive_pipeline.as_ref().unwrap(),
+                _ => shader_program.pipeline.as_ref().unwrap(),
+            };
+            command_buffer.bind_graphics_pipeline(pipeline);
+
+            // Bind buffers
+            let vertex_buffer_offset = (frame_idx * batch.vertex_buffer.2) as u64;
+            command_buffer.bind_vertex_buffers(
+                0,
+                Some((
+                    batch.vertex_buffer_ref(),
+                    buffer::SubRange {
+                        offset: vertex_buffer_offset,
+                        size: Some(batch.vertex_buffer.2 as u64),
+                    },
+                )),
+            );
+
+            let index_buffer_offset = (frame_idx * batch.index_buffer.2) as u64;
             command_buffer.bind_index_buffer(buffer::IndexBufferView {
                 buffer: batch.index_buffer_ref(),
                 range: buffer::SubRange {
@@ -1042,20 +1745,96 @@ impl Renderer {
                 shader_program.pipeline_layout.as_ref().unwrap(),
                 0,
                 vec![batch.descriptor_set_ref()],
-                &[],
+                &[uniform_buffer_offset],
             );
 
             command_buffer.draw_indexed(0..indices_len, 0, 0..1);
         }
     }
 
+    /// Grow a batch's vertex and index buffers, if they're too small for a mesh of the given size.
+    /// Capacity is rounded up to a power of two, so a batch that keeps growing only reallocates a few times.
+    /// If a buffer can't be grown, the batch keeps its old one.
+    fn reserve_batch_buffers(&mut self, batch_key: RenderKey, vertex_count: usize, index_count: usize) -> Result<(), BufferError> {
+        let batch = &self.batches[&batch_key];
+        let grow_vertices = (vertex_count * std::mem::size_of::<Vertex>()) > batch.vertex_buffer.2;
+        let grow_indices = (index_count * std::mem::size_of::<u32>()) > batch.index_buffer.2;
+        if !grow_vertices && !grow_indices {
+            return Ok(());
+        }
+
+        // Frames still in flight may be drawing from the old buffers, so they can't be freed until those are done.
+        // This only happens the first few times a batch gets bigger, so it's fine to stall for it.
+        self.device
+            .borrow()
+            .wait_idle()
+            .expect("Failed to wait for device idle!");
+
+        let batch = self.batches.get_mut(&batch_key).unwrap();
+        let mut old_buffers = Vec::new();
+        let mut result = Ok(());
+
+        if grow_vertices {
+            match create_vertex_buffer(
+                self.device.clone(),
+                &self.adapter.physical_device,
+                &[],
+                vertex_count.next_power_of_two(),
+                self.frames_in_flight,
+            ) {
+                Ok((buffer, memory, frame_len)) => {
+                    old_buffers.push(std::mem::replace(&mut batch.vertex_buffer, (Some(buffer), Some(memory), frame_len)));
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+
+        if grow_indices && result.is_ok() {
+            match create_index_buffer(
+                self.device.clone(),
+                &self.adapter.physical_device,
+                &[],
+                index_count.next_power_of_two(),
+                self.frames_in_flight,
+            ) {
+                Ok((buffer, memory, frame_len)) => {
+                    old_buffers.push(std::mem::replace(&mut batch.index_buffer, (Some(buffer), Some(memory), frame_len)));
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+
+        if result.is_ok() {
+            println!(
+                "[GFX] Grew render batch {} to {} vertices and {} indices",
+                batch_key,
+                batch.vertex_buffer.2 / std::mem::size_of::<Vertex>(),
+                batch.index_buffer.2 / std::mem::size_of::<u32>(),
+            );
+        }
+
+        let device = self.device.borrow();
+        for (buffer, memory, _) in old_buffers {
+            unsafe {
+                device.destroy_buffer(buffer.unwrap());
+                device.free_memory(memory.unwrap());
+            }
+        }
+
+        result
+    }
+
     pub fn rebuild_swapchain(&mut self) {
         if self.surface.is_none() {
             panic!("Failed to rebuild swapchain: Renderer surface was None!");
         }
         let surface = self.surface.as_mut().unwrap();
 
-        println!("Rebuilding swapchain.");
+        // A minimized window has no area, and a swapchain can't be made for it
+        if (self.dimensions.width == 0) || (self.dimensions.height == 0) {
+            self.is_swapchain_valid = false;
+            return;
+        }
 
         let capabilities = surface.capabilities(&self.adapter.physical_device);
         let swap_config = window::SwapchainConfig::from_caps(
@@ -1063,20 +1842,83 @@ impl Renderer {
             self.surface_color_format,
             self.dimensions,
         );
+
+        // Some platforms report the window's real size as zero while minimized, even if we haven't seen it resize yet
+        if (swap_config.extent.width == 0) || (swap_config.extent.height == 0) {
+            self.is_swapchain_valid = false;
+            return;
+        }
+
+        println!("Rebuilding swapchain.");
+
+        // FIFO is the only mode every surface has to support, so fall back to it
+        let present_mode = if capabilities.present_modes.contains(self.preferred_present_mode) {
+            self.preferred_present_mode
+        } else {
+            PresentMode::FIFO
+        };
+        let swap_config = swap_config.with_present_mode(present_mode);
         println!("swap_config: {:?}", swap_config);
+        println!("Using present mode {:?}", present_mode);
         let extent = swap_config.extent.to_extent();
 
-        unsafe {
-            surface
-                .configure_swapchain(&self.device.borrow(), swap_config)
-                .expect("Can't create swapchain");
+        if let Err(e) = unsafe { surface.configure_swapchain(&self.device.borrow(), swap_config) } {
+            eprintln!("[Renderer] Failed to configure swapchain: {:?}", e);
+            self.is_swapchain_valid = false;
+            return;
         }
+        self.is_swapchain_valid = true;
 
         self.viewport.rect.w = extent.width as _;
         self.viewport.rect.h = extent.height as _;
+
+        self.rebuild_msaa_target(extent.width, extent.height);
+    }
+
+    /// Recreate the multisampled color target at the given size. Does nothing without MSAA.
+    fn rebuild_msaa_target(&mut self, width: u32, height: u32) {
+        if self.sample_count <= 1 {
+            return;
+        }
+
+        // Frames still in flight may be drawing to the old target
+        self.device
+            .borrow()
+            .wait_idle()
+            .expect("Failed to wait for device idle!");
+
+        if let Some((image, memory, view)) = self.msaa_target.take() {
+            let device = self.device.borrow();
+            unsafe {
+                device.destroy_image_view(view);
+                device.destroy_image(image);
+                device.free_memory(memory);
+            }
+        }
+
+        // Only ever drawn to and resolved, so the samples never need to leave the GPU
+        self.msaa_target = Some(create_image(
+            self.device.clone(),
+            &self.adapter.physical_device,
+            width,
+            height,
+            self.surface_color_format,
+            img::Usage::COLOR_ATTACHMENT | img::Usage::TRANSIENT_ATTACHMENT,
+            Aspects::COLOR,
+            self.sample_count,
+        ));
     }
 
-    pub fn create_gpu_texture(&mut self, id: GpuTextureId, w: u32, h: u32, pixels: &Vec<u8>) {
+    /// Upload a texture. Pixel art should use `Filter::Nearest`, to keep it crisp when scaled.
+    pub fn create_gpu_texture(
+        &mut self,
+        id: GpuTextureId,
+        w: u32,
+        h: u32,
+        pixels: &Vec<u8>,
+        filter: Filter,
+        wrap: WrapMode,
+    ) {
         let (texture_image, texture_memory, texture_view) = create_image(
             self.device.clone(),
             &self.adapter.physical_device,
@@ -1085,12 +1927,13 @@ impl Renderer {
             Format::Rgba8Srgb,
             img::Usage::TRANSFER_DST | img::Usage::SAMPLED,
             Aspects::COLOR,
+            1,
         );
 
         let texture_sampler = unsafe {
             self.device
                 .borrow()
-                .create_sampler(&img::SamplerDesc::new(Filter::Nearest, WrapMode::Tile))
+                .create_sampler(&img::SamplerDesc::new(filter, wrap))
         }
         .expect("Failed to create sampler!");
 
@@ -1112,7 +1955,8 @@ impl Renderer {
                 buffer::Usage::TRANSFER_SRC,
                 Properties::CPU_VISIBLE,
                 upload_size as usize,
-            );
+            )
+            .expect("Failed to create texture upload buffer!");
 
             unsafe {
                 let mapping = self
@@ -1229,12 +2073,60 @@ impl Renderer {
             memory: Some(texture_memory),
             image_view: Some(texture_view),
             sampler: Some(texture_sampler),
+            filter,
+            wrap,
             w,
             h,
         };
 
         self.textures.insert(id, tex);
     }
+
+    /// Destroy a texture created with `create_gpu_texture`, along with any render batches that use it.
+    pub fn destroy_gpu_texture(&mut self, id: TextureId) -> Result<(), TextureError> {
+        if !self.textures.contains_key(&id) {
+            return Err(TextureError::NotFound(id));
+        }
+
+        let batch_keys: Vec<RenderKey> = self
+            .batches
+            .values()
+            .filter(|batch| batch.tex_id() == id)
+            .map(|batch| batch.key())
+            .collect();
+
+        if batch_keys
+            .iter()
+            .any(|key| self.batches[key].has_pending_mesh())
+        {
+            return Err(TextureError::InUse(id));
+        }
+
+        // Previous frames may still be using the texture
+        self.device
+            .borrow()
+            .wait_idle()
+            .expect("Failed to wait for device idle!");
+
+        // Batches hold descriptor sets pointing at the texture, so they need to go first
+        for key in batch_keys {
+            let mut batch = self.batches.remove(&key).unwrap();
+            if let Some(shader_program) = self.shader_programs.get_mut(&batch.shader_program_id) {
+                unsafe {
+                    shader_program
+                        .descriptor_pool
+                        .as_mut()
+                        .unwrap()
+                        .free_sets(batch.descriptor_set.take());
+                }
+            }
+        }
+
+        self.textures.remove(&id);
+        println!("[GFX] Destroyed texture {}", id);
+
+        Ok(())
+    }
 }
 
 impl Drop for Renderer {
@@ -1247,10 +2139,19 @@ impl Drop for Renderer {
 
         let device = self.device.borrow();
         unsafe {
-            self.instance.destroy_surface(self.surface.take().unwrap());
+            // Can be missing if recreating it after it was lost failed
+            if let Some(surface) = self.surface.take() {
+                self.instance.destroy_surface(surface);
+            }
 
             device.destroy_render_pass(self.render_pass.take().unwrap());
 
+            if let Some((image, memory, view)) = self.msaa_target.take() {
+                device.destroy_image_view(view);
+                device.destroy_image(image);
+                device.free_memory(memory);
+            }
+
             device.destroy_buffer(self.uniform_buffer.take().unwrap());
             device.free_memory(self.uniform_buffer_memory.take().unwrap());
 
@@ -1271,13 +2172,85 @@ impl Drop for Renderer {
     }
 }
 
+/// Sort commands by their batch key, and group the ones that can be drawn in the same batch.
+/// Groups are in draw order, and commands keep their submission order within a group.
+/// This doesn't touch the GPU, so it can be used without a renderer.
+pub fn group_commands<'a>(
+    commands: impl IntoIterator<Item = &'a RenderCommand>,
+) -> Vec<(RenderKey, Vec<&'a RenderCommand>)> {
+    let mut commands: Vec<&RenderCommand> = commands.into_iter().collect();
+
+    // Stable, so that commands with the same key are drawn in the order they were pushed
+    commands.sort_by_key(|command| command.key());
+
+    let mut groups: Vec<(RenderKey, Vec<&RenderCommand>)> = Vec::new();
+    for command in commands {
+        let key = command.key();
+        match groups.last_mut() {
+            Some((group_key, group)) if *group_key == key => group.push(command),
+            _ => groups.push((key, vec![command])),
+        }
+    }
+
+    groups
+}
+
+/// Pick the adapter to render with.
+/// Only adapters with a queue family that can draw to the surface are considered, and discrete GPUs are
+/// preferred over integrated ones. The choice can be overridden by name with the GFX_ADAPTER env var.
+fn select_adapter(adapters: Vec<GfxAdapter>, surface: &GfxSurface) -> Result<GfxAdapter, RendererError> {
+    let mut adapters: Vec<GfxAdapter> = adapters
+        .into_iter()
+        .filter(|adapter| {
+            adapter.queue_families.iter().any(|family| {
+                surface.supports_queue_family(family) && family.queue_type().supports_graphics()
+            })
+        })
+        .collect();
+
+    if adapters.is_empty() {
+        return Err(RendererError::NoSuitableAdapter);
+    }
+
+    if let Ok(name) = std::env::var(ADAPTER_OVERRIDE_ENV_VAR) {
+        let name = name.to_lowercase();
+        match adapters
+            .iter()
+            .position(|adapter| adapter.info.name.to_lowercase().contains(&name))
+        {
+            Some(index) => return Ok(adapters.remove(index)),
+            None => eprintln!(
+                "No adapter matching {}={} was found! Falling back to the default choice.",
+                ADAPTER_OVERRIDE_ENV_VAR, name
+            ),
+        }
+    }
+
+    let rank = |device_type: &DeviceType| match device_type {
+        DeviceType::DiscreteGpu => 3,
+        DeviceType::IntegratedGpu => 2,
+        DeviceType::VirtualGpu => 1,
+        _ => 0,
+    };
+
+    // Keep the enumeration order between adapters of the same type
+    let mut best = 0;
+    for (i, adapter) in adapters.iter().enumerate() {
+        if rank(&adapter.info.device_type) > rank(&adapters[best].info.device_type) {
+            best = i;
+        }
+    }
+
+    Ok(adapters.remove(best))
+}
+
 fn create_buffer(
     device: GfxDeviceHandle,
     physical_device: &dyn PhysicalDevice<backend::Backend>,
     usage: buffer::Usage,
     properties: Properties,
     buffer_len: usize,
-) -> (GfxBuffer, GfxMemory) {
+) -> Result<(GfxBuffer, GfxMemory), BufferError> {
     assert_ne!(buffer_len, 0);
 
     // Get a list of available memory types
@@ -1288,7 +2261,7 @@ fn create_buffer(
         device
             .borrow()
             .create_buffer(buffer_len as u64, usage)
-            .unwrap()
+            .map_err(BufferError::Creation)?
     };
 
     // Get the memory requirements for this buffer
@@ -1302,35 +2275,59 @@ fn create_buffer(
             let type_supported = mem_requirements.type_mask & (1_u64 << id) != 0;
             type_supported && ty.properties.contains(properties)
         })
-        .map(|(id, _ty)| MemoryTypeId(id))
-        .expect("Could not find appropriate buffer memory type!");
+        .map(|(id, _ty)| MemoryTypeId(id));
+    let upload_type = match upload_type {
+        Some(upload_type) => upload_type,
+        None => {
+            unsafe { device.borrow().destroy_buffer(buffer) };
+            return Err(BufferError::NoSuitableMemoryType);
+        }
+    };
 
     // Now allocate the memory and bind our buffer to it.
-    let buffer_memory = unsafe {
+    let buffer_memory = match unsafe {
         device
             .borrow()
             .allocate_memory(upload_type, mem_requirements.size)
-    }
-    .unwrap();
+    } {
+        Ok(memory) => memory,
+        Err(e) => {
+            unsafe { device.borrow().destroy_buffer(buffer) };
+            return Err(BufferError::Allocation(e));
+        }
+    };
 
-    unsafe {
+    let bind_result = unsafe {
         device
             .borrow()
             .bind_buffer_memory(&buffer_memory, 0, &mut buffer)
+    };
+    if let Err(e) = bind_result {
+        destroy_buffer(&device, buffer, buffer_memory);
+        return Err(BufferError::Bind(e));
     }
-    .unwrap();
 
-    (buffer, buffer_memory)
+    Ok((buffer, buffer_memory))
+}
+
+/// Free a buffer and its memory from `create_buffer`, for when something after it fails.
+fn destroy_buffer(device: &GfxDeviceHandle, buffer: GfxBuffer, memory: GfxMemory) {
+    let device = device.borrow();
+    unsafe {
+        device.destroy_buffer(buffer);
+        device.free_memory(memory);
+    }
 }
 
 fn create_vertex_buffer(
     device: GfxDeviceHandle,
     physical_device: &dyn PhysicalDevice<backend::Backend>,
     mesh: &[Vertex],
+    capacity: usize,
     frames_in_flight: usize,
-) -> (GfxBuffer, GfxMemory, usize) {
+) -> Result<(GfxBuffer, GfxMemory, usize), BufferError> {
     let stride = std::mem::size_of::<Vertex>();
-    let buffer_frame_len = (MAX_BATCH_VERTICES as usize) * stride;
+    let buffer_frame_len = capacity * stride;
 
     let (buffer, buffer_memory) = create_buffer(
         device.clone(),
@@ -1338,21 +2335,22 @@ fn create_vertex_buffer(
         buffer::Usage::VERTEX | buffer::Usage::TRANSFER_DST,
         Properties::CPU_VISIBLE,
         buffer_frame_len * frames_in_flight,
-    );
+    )?;
 
     update_buffer(&buffer_memory, 0, buffer_frame_len, device.clone(), mesh);
 
-    (buffer, buffer_memory, buffer_frame_len)
+    Ok((buffer, buffer_memory, buffer_frame_len))
 }
 
 fn create_index_buffer(
     device: GfxDeviceHandle,
     physical_device: &dyn PhysicalDevice<backend::Backend>,
     indices: &[u32],
+    capacity: usize,
     frames_in_flight: usize,
-) -> (GfxBuffer, GfxMemory, usize) {
+) -> Result<(GfxBuffer, GfxMemory, usize), BufferError> {
     let stride = std::mem::size_of::<u32>();
-    let buffer_frame_len = (MAX_BATCH_INDICES as usize) * stride;
+    let buffer_frame_len = capacity * stride;
 
     let (index_buffer, index_buffer_memory) = create_buffer(
         device.clone(),
@@ -1360,7 +2358,7 @@ fn create_index_buffer(
         buffer::Usage::INDEX | buffer::Usage::TRANSFER_DST,
         Properties::CPU_VISIBLE,
         buffer_frame_len * frames_in_flight,
-    );
+    )?;
 
     update_buffer(
         &index_buffer_memory,
@@ -1370,7 +2368,7 @@ fn create_index_buffer(
         indices,
     );
 
-    (index_buffer, index_buffer_memory, buffer_frame_len)
+    Ok((index_buffer, index_buffer_memory, buffer_frame_len))
 }
 
 fn create_uniform_buffer(
@@ -1378,8 +2376,11 @@ fn create_uniform_buffer(
     physical_device: &dyn PhysicalDevice<backend::Backend>,
     ubo: UniformBufferObject,
     frames_in_flight: usize,
-) -> (GfxBuffer, GfxMemory, usize) {
-    let buffer_frame_len = std::mem::size_of::<UniformBufferObject>();
+) -> Result<(GfxBuffer, GfxMemory, usize), BufferError> {
+    // Each frame's offset into the buffer has to meet the device's alignment for uniform buffer offsets
+    let alignment = (physical_device.limits().min_uniform_buffer_offset_alignment as usize).max(1);
+    let buffer_frame_len =
+        ((std::mem::size_of::<UniformBufferObject>() + alignment - 1) / alignment) * alignment;
 
     let (buffer, buffer_memory) = create_buffer(
         device.clone(),
@@ -1387,11 +2388,11 @@ fn create_uniform_buffer(
         buffer::Usage::UNIFORM | buffer::Usage::TRANSFER_DST,
         Properties::CPU_VISIBLE,
         buffer_frame_len * frames_in_flight,
-    );
+    )?;
 
     update_buffer(&buffer_memory, 0, buffer_frame_len, device.clone(), &[ubo]);
 
-    (buffer, buffer_memory, buffer_frame_len)
+    Ok((buffer, buffer_memory, buffer_frame_len))
 }
 
 fn update_buffer<T: Copy>(
@@ -1427,11 +2428,12 @@ fn create_image(
     format: Format,
     usage: img::Usage,
     aspects: Aspects,
+    samples: img::NumSamples,
 ) -> (GfxImage, GfxMemory, GfxImageView) {
     // Get a list of available memory types
     let memory_types = physical_device.memory_properties().memory_types;
 
-    let kind = img::Kind::D2(width, height, 1, 1);
+    let kind = img::Kind::D2(width, height, 1, samples);
 
     let mut image = unsafe {
         device.borrow().create_image(
@@ -1489,33 +2491,89 @@ fn create_image(
     (image, image_memory, image_view)
 }
 
+/// `final_layout` is the layout the color attachment is left in, which is `Layout::Present` for the swapchain.
+///
+/// With more than one sample, attachment 0 is the multisampled target and attachment 1 is the single sampled
+/// image it gets resolved into, which is the one left in `final_layout`.
 fn create_render_pass(
     device: GfxDeviceHandle,
     surface_color_fmt: Format,
     _depth_fmt: Format,
-) -> GfxRenderPass {
-    let color_attachment = Attachment {
+    final_layout: Layout,
+    samples: img::NumSamples,
+) -> Result<GfxRenderPass, OutOfMemory> {
+    if samples <= 1 {
+        let color_attachment = Attachment {
+            format: Some(surface_color_fmt),
+            samples: 1,
+            ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::Undefined..final_layout,
+        };
+
+        let subpass = SubpassDesc {
+            colors: &[(0, Layout::ColorAttachmentOptimal)],
+            depth_stencil: None,
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+
+        return unsafe {
+            device
+                .borrow()
+                .create_render_pass(&[color_attachment], &[subpass], &[])
+        };
+    }
+
+    // The samples are only needed until they're resolved, so they aren't stored
+    let msaa_attachment = Attachment {
+        format: Some(surface_color_fmt),
+        samples,
+        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::DontCare),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..Layout::ColorAttachmentOptimal,
+    };
+
+    // Every pixel is written by the resolve, so there's nothing to load
+    let resolve_attachment = Attachment {
         format: Some(surface_color_fmt),
         samples: 1,
-        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+        ops: AttachmentOps::new(AttachmentLoadOp::DontCare, AttachmentStoreOp::Store),
         stencil_ops: AttachmentOps::DONT_CARE,
-        layouts: Layout::Undefined..Layout::Present,
+        layouts: Layout::Undefined..final_layout,
     };
 
     let subpass = SubpassDesc {
         colors: &[(0, Layout::ColorAttachmentOptimal)],
         depth_stencil: None,
         inputs: &[],
-        resolves: &[],
+        resolves: &[(1, Layout::ColorAttachmentOptimal)],
         preserves: &[],
     };
 
     unsafe {
         device
             .borrow()
-            .create_render_pass(&[color_attachment], &[subpass], &[])
+            .create_render_pass(&[msaa_attachment, resolve_attachment], &[subpass], &[])
+    }
+}
+
+/// The highest sample count the adapter supports for color attachments, up to `requested`.
+/// Sample counts are powers of two, and every adapter supports 1.
+fn select_sample_count(physical_device: &dyn PhysicalDevice<backend::Backend>, requested: img::NumSamples) -> img::NumSamples {
+    let supported = physical_device.limits().framebuffer_color_sample_counts;
+
+    let mut samples = requested.max(1).next_power_of_two();
+    while (samples > 1) && ((samples > requested) || ((supported & samples) == 0)) {
+        samples /= 2;
+    }
+
+    if samples != requested {
+        eprintln!("[Renderer] {}x MSAA isn't supported, using {}x instead.", requested, samples);
     }
-    .expect("Failed to create render pass!")
+
+    samples
 }
 
 fn create_pipeline(
@@ -1523,8 +2581,10 @@ fn create_pipeline(
     vert_shader: &GfxShaderModule,
     frag_shader: &GfxShaderModule,
     render_pass: &GfxRenderPass,
+    samples: img::NumSamples,
     pipeline_layout: &GfxPipelineLayout,
     primitive: Primitive,
+    blend: pso::BlendState,
 ) -> GfxGraphicsPipeline {
     let vs_entry = EntryPoint::<backend::Backend> {
         entry: "main",
@@ -1559,9 +2619,24 @@ fn create_pipeline(
         subpass,
     );
 
+    // Line strips are batched together, separated by a restart index
+    if primitive == Primitive::LineStrip {
+        pipeline_desc.input_assembler.restart_index = Some(IndexType::U32);
+    }
+
+    if samples > 1 {
+        pipeline_desc.multisampling = Some(pso::Multisampling {
+            rasterization_samples: samples,
+            sample_shading: None,
+            sample_mask: !0,
+            alpha_coverage: false,
+            alpha_to_one: false,
+        });
+    }
+
     pipeline_desc.blender.targets.push(pso::ColorBlendDesc {
         mask: pso::ColorMask::ALL,
-        blend: Some(pso::BlendState::ALPHA),
+        blend: Some(blend),
     });
 
     // Let our pipeline know about the vertex buffers we are going to use
@@ -1610,41 +2685,39 @@ fn create_pipeline(
     .expect("Failed to create graphics pipeline!")
 }
 
-fn create_render_program(
-    device: GfxDeviceHandle,
-    render_pass: &GfxRenderPass,
-    vertex_shader_path: &str,
-    fragment_shader_path: &str,
-    shader_descriptor_bindings: Vec<ShaderDescriptorBinding>,
-    primitive: Primitive,
-) -> RenderProgram {
-    // Load shaders
-    let vert_shader = unsafe {
-        let mut file = File::open(vertex_shader_path).expect("Failed to open vertex shader file!");
-        let mut bytes = Vec::new();
-        file.read_to_end(&mut bytes)
-            .expect("Failed to read shader file into buffer!");
-
-        let spirv = pso::read_spirv(Cursor::new(&bytes[..])).expect("Failed to read spirv shader!");
+fn load_shader_module(device: GfxDeviceHandle, source: ShaderSource) -> Result<GfxShaderModule, ShaderError> {
+    let (name, bytes) = source;
+    let spirv = pso::read_spirv(Cursor::new(bytes)).map_err(|_| ShaderError::InvalidSpirv(name))?;
 
+    let module = unsafe {
         device
             .borrow()
             .create_shader_module(&spirv)
             .expect("Failed to create shader module!")
     };
-    let frag_shader = unsafe {
-        let mut file =
-            File::open(fragment_shader_path).expect("Failed to open fragment shader file!");
-        let mut bytes = Vec::new();
-        file.read_to_end(&mut bytes)
-            .expect("Failed to read shader file into buffer!");
 
-        let spirv = pso::read_spirv(Cursor::new(&bytes[..])).expect("Failed to read spirv shader!");
+    Ok(module)
+}
 
-        device
-            .borrow()
-            .create_shader_module(&spirv)
-            .expect("Failed to create shader module!")
+fn create_render_program(
+    device: GfxDeviceHandle,
+    render_pass: &GfxRenderPass,
+    samples: img::NumSamples,
+    vertex_shader: ShaderSource,
+    fragment_shader: ShaderSource,
+    shader_descriptor_bindings: Vec<ShaderDescriptorBinding>,
+    primitive: Primitive,
+) -> Result<RenderProgram, ShaderError> {
+    let vert_shader = load_shader_module(device.clone(), vertex_shader)?;
+    let frag_shader = match load_shader_module(device.clone(), fragment_shader) {
+        Ok(module) => module,
+        Err(e) => {
+            unsafe {
+                device.borrow().destroy_shader_module(vert_shader);
+            }
+
+            return Err(e);
+        }
     };
 
     let (bindings, descriptor_ranges) = {
@@ -1679,7 +2752,7 @@ fn create_render_program(
         device.borrow().create_descriptor_pool(
             MAX_DESCRIPTOR_SETS,
             &descriptor_ranges,
-            pso::DescriptorPoolCreateFlags::empty(),
+            pso::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
         )
     }
     .expect("Failed to create descriptor pool!");
@@ -1692,24 +2765,176 @@ fn create_render_program(
     }
     .expect("Failed to create pipeline layout!");
 
-    // Create the pipeline
+    // Create the pipelines
     let pipeline = create_pipeline(
         device.clone(),
         &vert_shader,
         &frag_shader,
         &render_pass,
+        samples,
         &pipeline_layout,
         primitive,
+        pso::BlendState::ALPHA,
     );
 
-    RenderProgram {
+    // Weighted by source alpha, so that transparent texels don't add anything
+    let additive_pipeline = create_pipeline(
+        device.clone(),
+        &vert_shader,
+        &frag_shader,
+        &render_pass,
+        samples,
+        &pipeline_layout,
+        primitive,
+        pso::BlendState {
+            color: pso::BlendOp::Add {
+                src: pso::Factor::SrcAlpha,
+                dst: pso::Factor::One,
+            },
+            alpha: pso::BlendOp::ADD,
+        },
+    );
+
+    Ok(RenderProgram {
         device,
         vert_shader: Some(vert_shader),
         frag_shader: Some(frag_shader),
         pipeline: Some(pipeline),
+        additive_pipeline: Some(additive_pipeline),
         pipeline_layout: Some(pipeline_layout),
         descriptor_pool: Some(descriptor_pool),
         descriptor_set_layout: Some(descriptor_set_layout),
         shader_descriptor_bindings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite_command(transparency: Transparency, layer: u8, shader_program_id: ShaderProgramId, tex_id: TextureId, x: f32) -> RenderCommand {
+        RenderCommand {
+            transparency,
+            shader_program_id,
+            tex_id,
+            layer,
+            z_order: 0,
+            data: Renderable::Sprite {
+                x,
+                y: 0.0,
+                pivot: Point2f::origin(),
+                scale: Vector2f::new(1.0, 1.0),
+                rotation: 0.0,
+                color: COLOR_WHITE,
+                region: SpriteRegion { x: 0, y: 0, w: 2, h: 2 },
+            },
+        }
+    }
+
+    fn sprite_x(command: &RenderCommand) -> f32 {
+        match command.data {
+            Renderable::Sprite { x, .. } => x,
+            _ => panic!("Expected a sprite command"),
+        }
+    }
+
+    #[test]
+    fn group_commands_groups_by_key_in_draw_order() {
+        let commands = vec![
+            sprite_command(Transparency::Opaque, 1, 1, 2, 0.0),
+            sprite_command(Transparency::Opaque, 0, 1, 3, 1.0),
+            sprite_command(Transparency::Transparent, 0, 1, 2, 2.0),
+            sprite_command(Transparency::Opaque, 1, 1, 2, 3.0),
+            sprite_command(Transparency::Opaque, 0, 0, 3, 4.0),
+            sprite_command(Transparency::Opaque, 0, 1, 2, 5.0),
+            sprite_command(Transparency::Opaque, 1, 1, 2, 6.0),
+        ];
+
+        let groups = group_commands(commands.iter());
+        assert_eq!(groups.len(), 5);
+
+        let expected_keys = [
+            RenderBatch::gen_key(Transparency::Opaque, 0, 0, 0, 3),
+            RenderBatch::gen_key(Transparency::Opaque, 0, 0, 1, 2),
+            RenderBatch::gen_key(Transparency::Opaque, 0, 0, 1, 3),
+            RenderBatch::gen_key(Transparency::Opaque, 1, 0, 1, 2),
+            RenderBatch::gen_key(Transparency::Transparent, 0, 0, 1, 2),
+        ];
+        let keys: Vec<RenderKey> = groups.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, expected_keys);
+
+        // Commands that share a key stay in the order they were submitted
+        let xs: Vec<Vec<f32>> = groups
+            .iter()
+            .map(|(_, group)| group.iter().map(|command| sprite_x(command)).collect())
+            .collect();
+        assert_eq!(xs, vec![vec![4.0], vec![5.0], vec![1.0], vec![0.0, 3.0, 6.0], vec![2.0]]);
+    }
+
+    #[test]
+    fn transparent_low_layer_orders_after_opaque_high_layer() {
+        let transparent = sprite_command(Transparency::Transparent, 0, 1, 1, 0.0);
+        let opaque = sprite_command(Transparency::Opaque, std::u8::MAX, 1, 1, 1.0);
+        assert!(transparent.key() > opaque.key());
+
+        let commands = vec![transparent, opaque];
+        let groups = group_commands(commands.iter());
+        assert_eq!(groups.len(), 2);
+        assert_eq!(sprite_x(groups[0].1[0]), 1.0);
+        assert_eq!(sprite_x(groups[1].1[0]), 0.0);
+    }
+
+    #[test]
+    fn higher_z_order_orders_after_lower_on_the_same_layer() {
+        // The higher z_order has the lower shader and texture ids, which would sort it first if they counted
+        let mut above = sprite_command(Transparency::Opaque, 3, 0, 0, 0.0);
+        above.z_order = 2;
+        let mut below = sprite_command(Transparency::Opaque, 3, 5, 9, 1.0);
+        below.z_order = 1;
+        assert!(above.key() > below.key());
+
+        let commands = vec![above, below];
+        let groups = group_commands(commands.iter());
+        assert_eq!(groups.len(), 2);
+        assert_eq!(sprite_x(groups[0].1[0]), 1.0);
+        assert_eq!(sprite_x(groups[1].1[0]), 0.0);
+    }
+
+    // Needs a window and a GPU, so it only runs when asked for with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn destroy_gpu_texture_after_drawing_with_it() {
+        use ::winit::{event_loop::EventLoop, platform::unix::EventLoopExtUnix, window::WindowBuilder};
+
+        let event_loop: EventLoop<()> = EventLoop::new_any_thread();
+        let window = WindowBuilder::new()
+            .with_visible(false)
+            .build(&event_loop)
+            .unwrap();
+        let mut renderer = Renderer::new(&window, 1.0, 2, 1).unwrap();
+        renderer.rebuild_swapchain();
+
+        let tex_id: TextureId = 7;
+        renderer.create_gpu_texture(tex_id, 2, 2, &vec![255; 16], Filter::Nearest, WrapMode::Clamp);
+
+        // Commands that haven't been rendered yet keep the texture alive
+        let commands = vec![sprite_command(Transparency::Opaque, 0, 1, tex_id, 0.0)];
+        let batch_keys = renderer.process_commands(commands.iter());
+        match renderer.destroy_gpu_texture(tex_id) {
+            Err(TextureError::InUse(id)) => assert_eq!(id, tex_id),
+            result => panic!("Expected the texture to be in use, got {:?}", result),
+        }
+
+        renderer.render(1.0, batch_keys).unwrap();
+        assert!(renderer.destroy_gpu_texture(tex_id).is_ok());
+        match renderer.destroy_gpu_texture(tex_id) {
+            Err(TextureError::NotFound(id)) => assert_eq!(id, tex_id),
+            result => panic!("Expected the texture to be gone, got {:?}", result),
+        }
+
+        // The next frame draws without the texture's batch
+        let batch_keys = renderer.process_commands(std::iter::empty());
+        renderer.render(1.0, batch_keys).unwrap();
     }
 }
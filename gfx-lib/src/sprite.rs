@@ -1,7 +1,37 @@
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct SpriteRegion {
     pub x: u32,
     pub y: u32,
     pub w: u32,
     pub h: u32,
 }
+
+// Font sheets lay out their glyphs in a 16x16 grid, in ascii order
+pub const FONT_SHEET_COLUMNS: u32 = 16;
+
+impl SpriteRegion {
+    /// The region of an ascii glyph in a font sheet with `cell_w` x `cell_h` cells.
+    pub fn glyph(ascii: u8, cell_w: u32, cell_h: u32) -> Self {
+        SpriteRegion {
+            x: (ascii as u32 % FONT_SHEET_COLUMNS) * cell_w,
+            y: (ascii as u32 / FONT_SHEET_COLUMNS) * cell_h,
+            w: cell_w,
+            h: cell_h,
+        }
+    }
+
+    /// Compute the UVs of this region within a spritesheet, in the order top left, top right, bottom right, bottom left.
+    pub fn uvs(&self, sheet_w: u32, sheet_h: u32) -> [(f32, f32); 4] {
+        let u: f32 = self.x as f32 / sheet_w as f32;
+        let v: f32 = self.y as f32 / sheet_h as f32;
+        let u_width: f32 = self.w as f32 / sheet_w as f32;
+        let v_height: f32 = self.h as f32 / sheet_h as f32;
+
+        [
+            (u, v),
+            (u + u_width, v),
+            (u + u_width, v + v_height),
+            (u, v + v_height),
+        ]
+    }
+}
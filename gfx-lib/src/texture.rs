@@ -13,4 +13,16 @@ impl Texture {
     pub fn id(&self) -> u16 {
         self.id
     }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
 }
@@ -1,37 +1,79 @@
-use crate::{input::InputState, renderer::Renderer};
+use crate::{
+    input::{InputState, VirtualKeyCode},
+    renderer::{RenderError, Renderer},
+};
 use ::winit::{
     dpi::LogicalSize,
+    event::ElementState,
     event::Event as WinitEvent,
     event::WindowEvent as WinitWindowEvent,
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Fullscreen, Icon, WindowBuilder},
 };
 use std::time::{Duration, Instant};
 
-pub use ::winit::window::Window as WinitWindow;
-
-const SIXTY_FPS_DT: f64 = 1.0 / 60.0;
+pub use ::winit::window::{CursorIcon, Window as WinitWindow};
 
 pub struct WindowState {
+    // Rendered frames per second
     pub fps: u32,
+    // Simulation ticks per second
+    pub sim_tps: u32,
+    // Logical size of the window, in render units
+    pub width: u32,
+    pub height: u32,
     pub window_scale: f32,
     pub dpi_scale_factor: f32,
+    // Set by the app to change the OS cursor
+    pub cursor_icon: CursorIcon,
+    // Set by the app to change the titlebar text
+    pub title: String,
 }
 
 pub type DeltaTime = f64;
 
+/// How to create the window and run its loop.
+pub struct WindowConfig<'a> {
+    pub title: &'a str,
+    // A PNG to use as the window icon
+    pub icon_path: Option<&'a str>,
+    // Logical size of the window, in render units
+    pub width: u32,
+    pub height: u32,
+    // Window pixels per render unit
+    pub render_scale: f32,
+    pub is_resizable: bool,
+    // Simulation ticks per second. Also the frame rate, if the frame rate is capped.
+    pub target_fps: u32,
+    pub is_frame_rate_capped: bool,
+    // How many frames the renderer can record while earlier ones are still on the GPU
+    pub frames_in_flight: usize,
+    // Samples per pixel for MSAA, where 1 turns it off
+    pub sample_count: u8,
+}
+
 pub fn run<T>(
-    title: &str,
-    width: u32,
-    height: u32,
-    render_scale: f32,
+    config: WindowConfig,
     app_state: T,
     init_callback: impl FnMut(&mut T, &mut Renderer) + 'static,
-    tick_callback: impl FnMut(&mut T, &WindowState, &InputState, DeltaTime) + 'static,
-    render_callback: impl FnMut(&T, u128, f64, &WindowState, &mut Renderer) + 'static,
+    tick_callback: impl FnMut(&mut T, &mut WindowState, &InputState, DeltaTime) + 'static,
+    render_callback: impl FnMut(&T, u128, f64, &WindowState, &mut Renderer) -> Result<(), RenderError> + 'static,
 ) where
     T: 'static,
 {
+    let WindowConfig {
+        title,
+        icon_path,
+        width,
+        height,
+        render_scale,
+        is_resizable,
+        target_fps,
+        is_frame_rate_capped,
+        frames_in_flight,
+        sample_count,
+    } = config;
+
     let event_loop = EventLoop::new();
     let window_size = LogicalSize::new(
         (width as f32 * render_scale) as u32,
@@ -41,7 +83,8 @@ pub fn run<T>(
         .with_title(title)
         .with_min_inner_size(window_size)
         .with_inner_size(window_size)
-        .with_resizable(false)
+        .with_resizable(is_resizable)
+        .with_window_icon(icon_path.and_then(load_icon))
         .build(&event_loop)
         .expect("Failed to create window!");
 
@@ -50,19 +93,41 @@ pub fn run<T>(
     let mut render_callback = Box::new(render_callback);
 
     let mut app_state: T = app_state;
-    let mut renderer: Renderer = Renderer::new(&window, render_scale);
+    let mut renderer: Renderer = match Renderer::new(&window, render_scale, frames_in_flight, sample_count) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            // Most likely an unsupported GPU or driver, so say what went wrong instead of panicking
+            eprintln!("[Renderer] Failed to initialize the renderer! {}", e);
+            std::process::exit(1);
+        }
+    };
     let mut input_state: InputState = InputState::new();
     let mut window_state = WindowState {
         fps: 0,
+        sim_tps: 0,
+        width,
+        height,
         window_scale: render_scale,
         dpi_scale_factor: window.scale_factor() as f32,
+        cursor_icon: CursorIcon::Default,
+        title: String::from(title),
     };
+    let mut current_cursor_icon = CursorIcon::Default;
+    let mut current_title = String::from(title);
+
+    #[cfg(feature = "gamepad")]
+    let mut gamepad_cursor = crate::gamepad::GamepadCursor::new();
 
     let one_second: Duration = Duration::from_secs(1);
     let mut fps_timer: Duration = Duration::from_secs(0);
     let mut fps_counter: u32 = 0;
+    let mut tps_counter: u32 = 0;
 
-    let target_dt: f64 = SIXTY_FPS_DT;
+    // The simulation always ticks at the target rate.
+    // If the frame rate is capped, we also only render at the target rate, and sleep in between frames.
+    let target_dt: f64 = 1.0 / target_fps as f64;
+    let target_frame_time: Duration = Duration::from_secs_f64(target_dt);
+    let mut next_frame_time = Instant::now();
     let mut time: f64 = 0.0;
     let mut current_time = Instant::now();
     let mut accumulator: f64 = 0.0;
@@ -74,7 +139,11 @@ pub fn run<T>(
     renderer.rebuild_swapchain();
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
+        *control_flow = if is_frame_rate_capped {
+            ControlFlow::WaitUntil(next_frame_time)
+        } else {
+            ControlFlow::Poll
+        };
 
         match event {
             WinitEvent::WindowEvent { event, window_id } => match event {
@@ -86,6 +155,16 @@ pub fn run<T>(
                 WinitWindowEvent::Resized(size) => {
                     println!("[Window] Resized to ({}, {})", size.width, size.height);
 
+                    // Minimizing resizes to zero. Keep the old logical size, so the game's layout doesn't collapse.
+                    if (size.width == 0) || (size.height == 0) {
+                        renderer.resize(size.width, size.height);
+                        return;
+                    }
+
+                    window_state.width =
+                        (size.width as f32 / window_state.dpi_scale_factor / render_scale) as u32;
+                    window_state.height =
+                        (size.height as f32 / window_state.dpi_scale_factor / render_scale) as u32;
                     renderer.resize(size.width, size.height);
                     window.request_redraw();
                 }
@@ -99,6 +178,10 @@ pub fn run<T>(
                     );
 
                     window_state.dpi_scale_factor = scale_factor as f32;
+                    window_state.width =
+                        (new_inner_size.width as f32 / window_state.dpi_scale_factor / render_scale) as u32;
+                    window_state.height =
+                        (new_inner_size.height as f32 / window_state.dpi_scale_factor / render_scale) as u32;
                     renderer.resize(new_inner_size.width, new_inner_size.height);
                     window.request_redraw();
                 }
@@ -115,6 +198,34 @@ pub fn run<T>(
                     }
 
                     input_state.handle_keyboard_input(&input);
+
+                    // F11 or Alt+Enter toggles borderless fullscreen
+                    let is_fullscreen_toggle_pressed = input.state == ElementState::Pressed
+                        && match input.virtual_keycode {
+                            Some(VirtualKeyCode::F11) => true,
+                            Some(VirtualKeyCode::Return) => input_state.is_alt_held(),
+                            _ => false,
+                        };
+
+                    if is_fullscreen_toggle_pressed {
+                        if window.fullscreen().is_some() {
+                            window.set_fullscreen(None);
+                        } else {
+                            window.set_fullscreen(Some(Fullscreen::Borderless(
+                                window.current_monitor(),
+                            )));
+                        }
+
+                        // Some platforms don't send a Resized event for this, so make sure the swapchain matches.
+                        // If one does come in, it will just rebuild the swapchain again with the final size.
+                        let size = window.inner_size();
+                        window_state.width =
+                            (size.width as f32 / window_state.dpi_scale_factor / render_scale) as u32;
+                        window_state.height =
+                            (size.height as f32 / window_state.dpi_scale_factor / render_scale) as u32;
+                        renderer.resize(size.width, size.height);
+                        window.request_redraw();
+                    }
                 }
                 WinitWindowEvent::MouseInput {
                     state,
@@ -128,39 +239,124 @@ pub fn run<T>(
                     ..
                 } => {
                     input_state.handle_cursor_movement(position);
+
+                    #[cfg(feature = "gamepad")]
+                    gamepad_cursor.handle_mouse_moved();
+                }
+                WinitWindowEvent::ModifiersChanged(modifiers) => {
+                    input_state.handle_modifiers_changed(modifiers);
+                }
+                WinitWindowEvent::Focused(is_focused) => {
+                    if !is_focused {
+                        input_state.handle_focus_lost();
+                    }
+                }
+                WinitWindowEvent::MouseWheel {
+                    delta,
+                    ..
+                } => {
+                    input_state.handle_scroll(delta);
                 }
                 _ => {}
             },
             WinitEvent::MainEventsCleared => {
+                if is_frame_rate_capped {
+                    let now = Instant::now();
+                    if now < next_frame_time {
+                        return;
+                    }
+
+                    // Schedule from the last frame time to avoid drifting, unless we have fallen far behind
+                    next_frame_time += target_frame_time;
+                    if next_frame_time < now {
+                        next_frame_time = now + target_frame_time;
+                    }
+
+                    *control_flow = ControlFlow::WaitUntil(next_frame_time);
+                }
+
                 let new_time = Instant::now();
                 frame_time = new_time - current_time;
                 frame_time = frame_time.min(std::time::Duration::from_secs_f64(0.1));
                 current_time = new_time;
 
                 let dt = frame_time.as_secs_f64();
+
+                #[cfg(feature = "gamepad")]
+                {
+                    let size = window.inner_size();
+                    gamepad_cursor.update(
+                        dt,
+                        (window_state.dpi_scale_factor * render_scale) as f64,
+                        (size.width, size.height),
+                        &mut input_state,
+                    );
+                }
+
                 accumulator += dt;
                 while accumulator >= target_dt {
-                    tick_callback(&mut app_state, &window_state, &input_state, dt);
+                    input_state.begin_tick(target_dt);
+                    tick_callback(&mut app_state, &mut window_state, &input_state, target_dt);
                     input_state.clear_pressed_and_released();
 
                     accumulator -= target_dt;
                     time += target_dt;
                     ticks += 1;
-                    fps_counter += 1;
+                    tps_counter += 1;
                 }
 
                 fps_timer = fps_timer + frame_time;
                 if fps_timer >= one_second {
                     fps_timer = std::time::Duration::from_secs(0);
                     window_state.fps = fps_counter;
+                    window_state.sim_tps = tps_counter;
                     fps_counter = 0;
+                    tps_counter = 0;
+                }
+
+                if window_state.cursor_icon != current_cursor_icon {
+                    current_cursor_icon = window_state.cursor_icon;
+                    window.set_cursor_icon(current_cursor_icon);
+                }
+
+                if window_state.title != current_title {
+                    current_title = window_state.title.clone();
+                    window.set_title(&current_title);
                 }
 
                 let lerp = accumulator / target_dt;
-                render_callback(&app_state, ticks, lerp, &window_state, &mut renderer);
+                if let Err(RenderError::SurfaceLost) =
+                    render_callback(&app_state, ticks, lerp, &window_state, &mut renderer)
+                {
+                    if let Err(e) = renderer.recreate_surface(&window) {
+                        eprintln!("[Window] {}", e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+                fps_counter += 1;
                 window.request_redraw();
             }
             _ => (),
         }
     });
 }
+
+fn load_icon(path: &str) -> Option<Icon> {
+    let image = match image::open(path) {
+        Ok(image) => image.to_rgba(),
+        Err(e) => {
+            eprintln!("[Window] Failed to open icon {}: {}", path, e);
+            return None;
+        }
+    };
+
+    let (width, height) = image.dimensions();
+    match Icon::from_rgba(image.into_raw(), width, height) {
+        Ok(icon) => Some(icon),
+        Err(e) => {
+            eprintln!("[Window] Failed to create icon from {}: {}", path, e);
+            None
+        }
+    }
+}
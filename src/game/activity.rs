@@ -1,6 +1,8 @@
-use crate::game::*;
+use crate::game::{rng::GameRng, *};
+use gfx::input::*;
 use specs::prelude::*;
 use rand::{seq::SliceRandom, Rng};
+use std::collections::HashMap;
 
 #[derive(Clone)]
 pub struct RandomHappening {
@@ -12,13 +14,34 @@ pub struct RandomHappening {
     pub message: String,
 }
 
+const ACTIVITY_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
+
+/// Whether a key is used for something outside the game itself, like pausing, saving, or taking a screenshot.
+/// These shouldn't also count as the "press any key" that starts a new run.
+fn is_hotkey(key: VirtualKeyCode) -> bool {
+    let is_function_key = (VirtualKeyCode::F1 as u32..=VirtualKeyCode::F24 as u32).contains(&(key as u32));
+    is_function_key || (key == VirtualKeyCode::Escape) || (key == VirtualKeyCode::P)
+}
+
 pub struct ActivityComponent {
     activity: Activity,
+    // The order this activity's button was spawned in, used for keyboard selection
+    index: usize,
 }
 
 impl ActivityComponent {
-    pub fn new(activity: Activity) -> Self {
-        ActivityComponent { activity }
+    pub fn new(activity: Activity, index: usize) -> Self {
+        ActivityComponent { activity, index }
     }
 }
 
@@ -26,6 +49,16 @@ impl Component for ActivityComponent {
     type Storage = VecStorage<Self>;
 }
 
+/// The activity of a clicked button. Buttons are rebuilt after every click, so a queued click
+/// can refer to a button that has since been deleted, in which case there's nothing to do.
+fn clicked_activity<'a>(ents: &Entities, activity_comps: &'a ReadStorage<ActivityComponent>, ent: Entity) -> Option<&'a ActivityComponent> {
+    if !ents.is_alive(ent) {
+        return None;
+    }
+
+    activity_comps.get(ent)
+}
+
 #[derive(Clone)]
 pub struct Activity {
     pub name: String,
@@ -35,6 +68,15 @@ pub struct Activity {
     pub effects: Vec<StatEffect>,
     pub condition_effects: Vec<ConditionEffect>,
     pub conditions: Vec<GameCondition>,
+    pub cooldown: Cooldown,
+}
+
+/// How often an activity can be done.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Cooldown {
+    Unlimited,
+    OncePerDay,
+    OncePerTimeOfDay,
 }
 
 #[derive(Default)]
@@ -43,15 +85,30 @@ pub struct ActivityState {
     pub happenings: Vec<RandomHappening>,
     pub is_rebuild_required: bool,
     pub last_happening_id: Option<i32>,
+    // Activities that can't be done again until their cooldown resets, by name
+    pub cooldowns: HashMap<String, Cooldown>,
 }
 
 impl ActivityState {
     pub fn new() -> Self {
+        let happenings = create_happenings();
+
+        // Happenings are told apart by id, so they must be unique
+        if cfg!(debug_assertions) {
+            let mut ids = std::collections::HashSet::new();
+            for happening in happenings.iter() {
+                if !ids.insert(happening.id) {
+                    panic!("Duplicate RandomHappening id {}!", happening.id);
+                }
+            }
+        }
+
         ActivityState {
             activities: create_activities(),
-            happenings: create_happenings(),
+            happenings,
             is_rebuild_required: false,
             last_happening_id: None,
+            cooldowns: HashMap::new(),
         }
     }
 }
@@ -67,10 +124,15 @@ impl<'a> System<'a> for ActivitySystem {
         Entities<'a>,
         WriteExpect<'a, ActivityState>,
         ReadExpect<'a, StatsState>,
-        ReadExpect<'a, EventChannel<OnClickedEvent>>,
+        WriteExpect<'a, GameRng>,
+        ReadExpect<'a, InputState>,
+        ReadExpect<'a, MerchantState>,
+        ReadExpect<'a, WeatherState>,
+        WriteExpect<'a, EventChannel<OnClickedEvent>>,
         WriteExpect<'a, EventChannel<GameEvent>>,
         WriteExpect<'a, EventChannel<LogEvent>>,
         ReadStorage<'a, ActivityComponent>,
+        ReadStorage<'a, ClickableComponent>,
     );
 
     fn setup(&mut self, world: &mut World) {
@@ -91,51 +153,47 @@ impl<'a> System<'a> for ActivitySystem {
 
     fn run(
         &mut self,
-        (ents, mut activity_state, stats, on_clicked_events, mut game_events, mut log_events, activity_comps): Self::SystemData,
+        (ents, mut activity_state, stats, mut rng, input, merchant_state, weather, mut on_clicked_events, mut game_events, mut log_events, activity_comps, clickables): Self::SystemData,
     ) {
         let mut queued_happening: Option<RandomHappening> = None;
         for event in game_events.read(&mut self.game_event_reader.as_mut().unwrap()) {
             match event {
-                GameEvent::GameOver | GameEvent::RefreshActivities => {
+                GameEvent::Victory | GameEvent::RefreshActivities => {
+                    activity_state.is_rebuild_required = true;
+                }
+                GameEvent::NewGameStarted => {
+                    *activity_state = ActivityState::new();
                     activity_state.is_rebuild_required = true;
                 }
                 GameEvent::NewDayStarted { .. } => {
-                    // TODO check for mail (or use a MailSystem)
+                    activity_state.cooldowns.clear();
                 }
                 GameEvent::NewTimeOfDayStarted { .. } => {
                     activity_state.is_rebuild_required = true;
+                    activity_state.cooldowns.retain(|_, cooldown| *cooldown == Cooldown::OncePerDay);
 
                     // Choose and run a random event
-                    let mut rng = rand::thread_rng();
-                    let mut happenings = activity_state.happenings.clone();
-                    happenings.shuffle(&mut rng);
-                    for happening in happenings {
-                        if let Some(id) = activity_state.last_happening_id {
-                            // Don't run the same happening twice in a row
-                            if happening.id == id {
-                                continue;
-                            }
-                        }
-
-                        for condition in happening.conditions.iter() {
-                            if !stats.condition(*condition) {
-                                continue;
-                            }
-                        }
-
-                        let roll: f32 = rng.gen();
-                        if roll < happening.chance {
-                            queued_happening = Some(happening);
-                            continue;
-                        }
-                    }
+                    queued_happening = choose_happening(
+                        &activity_state.happenings,
+                        activity_state.last_happening_id,
+                        &stats,
+                        &weather,
+                        &mut *rng,
+                    );
                 }
                 _ => {}
             }
         }
 
+        // Start a new run from the game over screen
+        let is_restart_pressed = input.pressed_keys_iter().any(|key| !is_hotkey(key)) || input.any_mouse_pressed();
+        if stats.condition(GameCondition::GameOver) && is_restart_pressed {
+            game_events.single_write(GameEvent::NewGameStarted);
+            log_events.single_write(LogEvent { message: String::from("A new keeper arrives at the lighthouse."), color: COLOR_YELLOW, reveal: false });
+        }
+
         if let Some(happening) = queued_happening {
-            log_events.single_write(LogEvent { message: happening.message.clone(), color: COLOR_BLUE });
+            log_events.single_write(LogEvent { message: happening.message.clone(), color: COLOR_BLUE, reveal: true });
             game_events.single_write(GameEvent::HandleStatEffects {
                 effects: happening.stat_effects,
             });
@@ -147,10 +205,38 @@ impl<'a> System<'a> for ActivitySystem {
             activity_state.last_happening_id = Some(happening.id);
         }
 
+        // Number keys select the Nth activity, as if its button was clicked.
+        // The merchant uses the number keys for purchases while it is here, so leave them alone then.
+        if !merchant_state.has_arrived() {
+            for key in input.pressed_keys_iter() {
+                let i = match ACTIVITY_KEYS.iter().position(|k| *k == key) {
+                    Some(i) => i,
+                    None => continue,
+                };
+
+                if let Some((ent, _, clickable)) = (&ents, &activity_comps, &clickables)
+                    .join()
+                    .find(|(_, comp, _)| comp.index == i)
+                {
+                    if !clickable.disabled {
+                        on_clicked_events.single_write(OnClickedEvent { ent });
+                    }
+                }
+            }
+        }
+
         for event in on_clicked_events.read(&mut self.on_clicked_event_reader.as_mut().unwrap()) {
-            if let Some(comp) = activity_comps.get(event.ent) {
+            if clickables.get(event.ent).map_or(false, |clickable| clickable.disabled) {
+                continue;
+            }
+
+            if let Some(comp) = clicked_activity(&ents, &activity_comps, event.ent) {
                 if !comp.activity.message.is_empty() {
-                    log_events.single_write(LogEvent { message: comp.activity.message.clone(), color: COLOR_BLACK });
+                    log_events.single_write(LogEvent { message: comp.activity.message.clone(), color: COLOR_BLACK, reveal: false });
+                }
+
+                if comp.activity.cooldown != Cooldown::Unlimited {
+                    activity_state.cooldowns.insert(comp.activity.name.clone(), comp.activity.cooldown);
                 }
 
                 game_events.single_write(comp.activity.event.clone());
@@ -179,6 +265,7 @@ pub fn create_activities() -> Vec<Activity> {
             effects: vec![],
             condition_effects: vec![],
             conditions: vec![],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Walk on the Beach"),
@@ -188,6 +275,7 @@ pub fn create_activities() -> Vec<Activity> {
             effects: vec![],
             condition_effects: vec![],
             conditions: vec![],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Paint a Picture"),
@@ -197,6 +285,7 @@ pub fn create_activities() -> Vec<Activity> {
             effects: vec![StatEffect::Add { stat: Stat::Sanity, amount: 2 }],
             condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::Inspired }],
             conditions: vec![GameCondition::Inspired],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Perform Maintenance"),
@@ -209,6 +298,7 @@ pub fn create_activities() -> Vec<Activity> {
             }],
             condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::LighthouseDamaged }],
             conditions: vec![GameCondition::LighthouseDamaged],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Repair Lens"),
@@ -221,6 +311,7 @@ pub fn create_activities() -> Vec<Activity> {
             }],
             condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::LensBroken }],
             conditions: vec![GameCondition::LensBroken],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Repair Generator"),
@@ -233,6 +324,20 @@ pub fn create_activities() -> Vec<Activity> {
             }],
             condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::GeneratorBroken }],
             conditions: vec![GameCondition::GeneratorBroken],
+            cooldown: Cooldown::Unlimited,
+        },
+        Activity {
+            name: String::from("Refuel Generator"),
+            message: String::from("You pour a can of gas into the generator."),
+            hours_required: 1,
+            event: GameEvent::None,
+            effects: vec![StatEffect::Subtract {
+                stat: Stat::Gas,
+                amount: 1,
+            }],
+            condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::GeneratorOutOfGas }],
+            conditions: vec![GameCondition::GeneratorOutOfGas],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Pray To Jand"),
@@ -245,6 +350,7 @@ pub fn create_activities() -> Vec<Activity> {
             }],
             condition_effects: vec![],
             conditions: vec![],
+            cooldown: Cooldown::OncePerDay,
         },
         Activity {
             name: String::from("Have a Drink"),
@@ -263,6 +369,7 @@ pub fn create_activities() -> Vec<Activity> {
             ],
             condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::Dread }],
             conditions: vec![GameCondition::Dread],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Lay in Bed"),
@@ -272,6 +379,7 @@ pub fn create_activities() -> Vec<Activity> {
             effects: vec![],
             condition_effects: vec![ConditionEffect::Clear { condition: GameCondition::Dread }],
             conditions: vec![GameCondition::Dread],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
             name: String::from("Hunt Rats"),
@@ -290,19 +398,53 @@ pub fn create_activities() -> Vec<Activity> {
             ],
             condition_effects: vec![],
             conditions: vec![GameCondition::Starving],
+            cooldown: Cooldown::Unlimited,
         },
         Activity {
-            name: String::from("End Game (TODO)"),
-            message: String::from("The game is now over."),
+            name: String::from("Hand Over The Light"),
+            message: String::from("The supply boat brings your relief. Your watch is over, and the light still burns."),
             hours_required: 0,
-            event: GameEvent::GameOver,
+            event: GameEvent::Victory,
             effects: vec![],
             condition_effects: vec![],
             conditions: vec![GameCondition::FinalDay],
+            cooldown: Cooldown::Unlimited,
         },
     ]
 }
 
+/// Roll for happenings in a random order, and return the first one that succeeds.
+/// A happening can only be rolled if all of its conditions are met, and it wasn't the last one to run.
+/// The weather scales the chance of each happening.
+pub fn choose_happening<R: Rng + ?Sized>(
+    happenings: &[RandomHappening],
+    last_happening_id: Option<i32>,
+    stats: &StatsState,
+    weather: &WeatherState,
+    rng: &mut R,
+) -> Option<RandomHappening> {
+    let mut happenings = happenings.to_vec();
+    happenings.shuffle(rng);
+
+    for happening in happenings {
+        // Don't run the same happening twice in a row
+        if Some(happening.id) == last_happening_id {
+            continue;
+        }
+
+        if !happening.conditions.iter().all(|condition| stats.condition(*condition)) {
+            continue;
+        }
+
+        let roll: f32 = rng.gen();
+        if roll < (happening.chance * weather.happening_chance_multiplier(&happening)) {
+            return Some(happening);
+        }
+    }
+
+    None
+}
+
 pub fn create_happenings() -> Vec<RandomHappening> {
     vec![
         RandomHappening {
@@ -410,7 +552,7 @@ pub fn create_happenings() -> Vec<RandomHappening> {
             conditions: vec![],
         },
         RandomHappening {
-            id: 12,
+            id: 13,
             message: String::from("You are feeling inspired and creative."),
             chance: 0.1,
             stat_effects: vec![],
@@ -421,17 +563,27 @@ pub fn create_happenings() -> Vec<RandomHappening> {
 }
 
 pub fn create_activity_ents(world: &mut World) {
-    let collision_groups = CollisionGroups::new();
     let button_bg_sprite_region = SpriteRegion {
         x: 0,
         y: 160,
         w: 160,
         h: 96,
     };
+    let button_hovered_sprite_region = SpriteRegion {
+        x: 160,
+        y: 160,
+        w: 160,
+        h: 96,
+    };
+
+    // Keep the particles that are already falling, so the rain doesn't restart whenever the buttons change
+    let emitters = take_particle_emitters(world);
 
     world.delete_all();
     world.maintain();
 
+    create_weather_ents(world, emitters);
+
     if world.read_resource::<StatsState>().condition(GameCondition::GameOver) {
         // Don't create new activities if the game is over
         return;
@@ -443,6 +595,8 @@ pub fn create_activity_ents(world: &mut World) {
     let mut counter = 0;
     for activity in activities {
         let mut are_conditions_satisfied = true;
+        // Activities that can't be paid for are still shown, but can't be chosen
+        let mut is_affordable = true;
         {
             let stats = world.read_resource::<StatsState>();
             for condition in activity.conditions.iter() {
@@ -456,7 +610,7 @@ pub fn create_activity_ents(world: &mut World) {
                 match effect {
                     StatEffect::Subtract { stat, amount } => {
                         if stats.stat(*stat) < *amount {
-                            are_conditions_satisfied = false;
+                            is_affordable = false;
                             break;
                         }
                     },
@@ -465,12 +619,16 @@ pub fn create_activity_ents(world: &mut World) {
             }
         }
 
-        let (time_of_day, hours_passed) = {
+        let (time_of_day, hours_remaining) = {
             let time = world.read_resource::<TimeState>();
-            (time.time_of_day, time.hours_passed)
+            (time.time_of_day, time.hours_remaining())
         };
 
-        if (time_of_day == TimeOfDay::Night) && ((hours_passed + activity.hours_required) > 4) {
+        if (time_of_day == TimeOfDay::Night) && (activity.hours_required > hours_remaining) {
+            are_conditions_satisfied = false;
+        }
+
+        if world.read_resource::<ActivityState>().cooldowns.contains_key(&activity.name) {
             are_conditions_satisfied = false;
         }
 
@@ -478,32 +636,16 @@ pub fn create_activity_ents(world: &mut World) {
             continue;
         }
 
-        world
-            .create_entity()
-            .with(TransformComponent::new(
-                Vector2d::new(layout_pos_x, layout_pos_y),
-                Vector2f::new(1.5, 1.0),
-            ))
-            .with(ColliderComponent::new(
-                Cuboid::new(Vector2d::new(
-                    (240.0 / 2.0) * PIXELS_TO_WORLD_UNITS,
-                    (96.0 / 2.0) * PIXELS_TO_WORLD_UNITS,
-                )),
-                Vector2d::zeros(),
-                collision_groups,
-                0.0,
-            ))
-            .with(ActivityComponent::new(activity.clone()))
-            .with(ClickableComponent::new())
-            .with(SpriteComponent::new(
-                button_bg_sprite_region,
-                resources::TEX_SPRITESHEET_UI,
-                Point2f::origin(),
-                COLOR_WHITE,
-                layers::LAYER_BUTTONS,
-                Transparency::Opaque,
-            ))
-            .build();
+        spawn_button(
+            world,
+            Vector2d::new(layout_pos_x, layout_pos_y),
+            Vector2d::new(240.0, 96.0),
+            button_bg_sprite_region,
+            Some(button_hovered_sprite_region),
+            !is_affordable,
+        )
+        .with(ActivityComponent::new(activity.clone(), counter))
+        .build();
 
         layout_pos_y += 100.0;
 
@@ -515,6 +657,8 @@ pub fn create_activity_ents(world: &mut World) {
         counter += 1;
     }
 
+    create_mail_ents(world);
+
     world.write_resource::<ActivityState>().is_rebuild_required = false;
 }
 
@@ -525,26 +669,43 @@ impl<'a> System<'a> for ActivityInfoRenderSystem {
     type SystemData = (
         Write<'a, RenderState>,
         ReadExpect<'a, StatsState>,
+        ReadExpect<'a, TimeState>,
         ReadStorage<'a, TransformComponent>,
         ReadStorage<'a, ActivityComponent>,
+        ReadStorage<'a, ClickableComponent>,
     );
 
-    fn run(&mut self, (mut render, stats, transforms, activity_comps): Self::SystemData) {
-        for (transform, activity) in (&transforms, &activity_comps).join() {
+    fn run(&mut self, (mut render, stats, time, transforms, activity_comps, clickables): Self::SystemData) {
+        let mut render = render.scope();
+
+        for (transform, activity, clickable) in (&transforms, &activity_comps, &clickables).join() {
             let x = transform.position.x as f32 + 16.0;
             let y = transform.position.y as f32 + 12.0;
             render.bind_transparency(Transparency::Opaque);
-            render.bind_layer(layers::LAYER_UI);
+            render.bind_layer(layers::Layer::Ui);
             render.bind_texture(resources::TEX_FONT);
-            render.bind_color(COLOR_BLACK);
-            render.text(x, y, 8, 16, 1.2, &activity.activity.name);
+            render.bind_color(if clickable.disabled { COLOR_GRAY } else { COLOR_BLACK });
+            let name_text = if activity.index < ACTIVITY_KEYS.len() {
+                format!("{}. {}", activity.index + 1, activity.activity.name)
+            } else {
+                activity.activity.name.clone()
+            };
+
+            render.text(x, y, 8, 16, 1.2, &name_text);
 
-            let hours_text = if activity.activity.hours_required == 1 {
+            let mut hours_text = if activity.activity.hours_required == 1 {
                 format!("{} hour", activity.activity.hours_required)
             } else {
                 format!("{} hours", activity.activity.hours_required)
             };
 
+            // Finishing the night's hours sends you to bed, so make it clear which activities will do that
+            let is_day_ending = (time.time_of_day == TimeOfDay::Night)
+                && (activity.activity.hours_required >= time.hours_remaining());
+            if is_day_ending {
+                hours_text += " (ends the day)";
+            }
+
             render.text(x, y + 20.0, 8, 16, 1.0, &hours_text);
 
             let effect_text = {
@@ -574,21 +735,223 @@ impl<'a> System<'a> for ActivityInfoRenderSystem {
 
         // Game Over screen
         if stats.condition(GameCondition::GameOver) {
-            let pos_x = 700.0;
+            let center_x = 840.0;
             let pos_y = 250.0;
 
             // Render text
             render.bind_texture(resources::TEX_FONT);
             render.bind_color(COLOR_BLACK);
-            render.text(pos_x + 16.0,pos_y + 16.0, 8, 16, 2.0, "Game Over");
+            let is_won = stats.condition(GameCondition::Won);
+            let title_text = if is_won { "You Survived" } else { "Game Over" };
+            render.text_aligned(center_x, pos_y + 16.0, 8, 16, 2.0, TextAlign::Center, title_text);
+
+            let reason_text = match stats.game_over_reason {
+                _ if is_won => format!("You kept the light burning for {} days", time.day),
+                Some(GameOverReason::Starved) => format!("You starved on Day {}", time.day),
+                Some(GameOverReason::Insane) => format!("You lost your mind on Day {}", time.day),
+                Some(GameOverReason::FinalDay) => format!("You kept the light until Day {}", time.day),
+                None => format!("Your watch ended on Day {}", time.day),
+            };
+
+            render.text_aligned(center_x, pos_y + 50.0, 8, 16, 1.0, TextAlign::Center, &reason_text);
+            render.text_aligned(
+                center_x,
+                pos_y + 70.0,
+                8,
+                16,
+                1.0,
+                TextAlign::Center,
+                &format!("Money earned: ${}", stats.total_money_earned),
+            );
+
+            let mut prompt_pos_y = pos_y + 100.0;
+            if is_won {
+                render.text_aligned(
+                    center_x,
+                    pos_y + 90.0,
+                    8,
+                    16,
+                    1.0,
+                    TextAlign::Center,
+                    &format!("You leave the island with ${}", stats.stat(Stat::Money)),
+                );
+                prompt_pos_y += 20.0;
+            }
+
+            render.text_aligned(
+                center_x,
+                prompt_pos_y,
+                8,
+                16,
+                1.0,
+                TextAlign::Center,
+                "Press any key to start a new game",
+            );
+        }
+    }
+}
+
+const TOOLTIP_WRAP_WIDTH: f32 = 280.0;
+const TOOLTIP_PADDING: f32 = 8.0;
+const TOOLTIP_LINE_HEIGHT: f32 = 18.0;
+
+/// Draws a panel next to the cursor describing the hovered activity.
+#[derive(Default)]
+pub struct ActivityTooltipRenderSystem;
+
+impl<'a> System<'a> for ActivityTooltipRenderSystem {
+    type SystemData = (
+        Write<'a, RenderState>,
+        ReadExpect<'a, InputState>,
+        ReadExpect<'a, PointerState>,
+        ReadExpect<'a, ScreenState>,
+        ReadStorage<'a, ActivityComponent>,
+    );
+
+    fn run(&mut self, (mut render, input, pointer, screen, activity_comps): Self::SystemData) {
+        let mut render = render.scope();
+
+        let activity = match pointer.hovered.and_then(|ent| activity_comps.get(ent)) {
+            Some(comp) => &comp.activity,
+            None => return,
+        };
+
+        render.bind_transparency(Transparency::Transparent);
+        render.bind_layer(layers::Layer::Tooltip);
+        render.bind_texture(resources::TEX_FONT);
+
+        let mut lines = render::wrap_text(&activity.message, TOOLTIP_WRAP_WIDTH, |text| {
+            render.measure_text(text, 8, 16, 1.0).0
+        });
+        for condition in activity.conditions.iter() {
+            lines.push(format!("Requires: {}", condition));
+        }
+
+        for effect in activity.condition_effects.iter() {
+            match effect {
+                ConditionEffect::Set { condition } => lines.push(format!("Causes: {}", condition)),
+                ConditionEffect::Clear { condition } => lines.push(format!("Fixes: {}", condition)),
+            }
+        }
+
+        let text_w = lines
+            .iter()
+            .map(|line| render.measure_text(line, 8, 16, 1.0).0)
+            .fold(0.0, f32::max);
+        let w = text_w + (TOOLTIP_PADDING * 2.0);
+        let h = (lines.len() as f32 * TOOLTIP_LINE_HEIGHT) + (TOOLTIP_PADDING * 2.0);
+
+        // Keep the panel on screen
+        let cursor = input.cursor_pos_logical(screen.dpi_scale_factor, screen.render_scale);
+        let x = (cursor.x as f32 + 16.0).min(screen.width as f32 - w).max(0.0);
+        let y = (cursor.y as f32 + 16.0).min(screen.height as f32 - h).max(0.0);
+
+        render.rect(x, y, w, h, COLOR_WHITE.with_alpha(0.9));
+        render.line(&[(x, y), (x + w, y), (x + w, y + h), (x, y + h), (x, y)], COLOR_BLACK);
+
+        // The panel is untextured, so without this the text would only be on top because of shader ids
+        render.bind_z_order(1);
+        render.bind_color(COLOR_BLACK);
+        for (i, line) in lines.iter().enumerate() {
             render.text(
-                pos_x + 16.0,
-                pos_y + 50.0,
+                x + TOOLTIP_PADDING,
+                y + TOOLTIP_PADDING + (i as f32 * TOOLTIP_LINE_HEIGHT),
                 8,
                 16,
                 1.0,
-                &format!("{}", "TODO"),
+                line,
             );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    fn happening(id: i32, conditions: Vec<GameCondition>) -> RandomHappening {
+        RandomHappening {
+            id,
+            chance: 0.5,
+            stat_effects: vec![],
+            condition_effects: vec![],
+            conditions,
+            message: format!("Happening {}", id),
+        }
+    }
+
+    // Every roll from this comes out as 0, so every eligible happening would succeed
+    fn always_succeeds() -> StepRng {
+        StepRng::new(0, 0)
+    }
+
+    #[test]
+    fn choose_happening_picks_exactly_one() {
+        let happenings = vec![happening(0, vec![]), happening(1, vec![]), happening(2, vec![])];
+        let stats = StatsState::new();
+        let weather = WeatherState::new();
+
+        let chosen = choose_happening(&happenings, None, &stats, &weather, &mut always_succeeds());
+        assert!(chosen.is_some());
+    }
+
+    #[test]
+    fn choose_happening_none_when_every_roll_fails() {
+        let happenings = vec![happening(0, vec![]), happening(1, vec![])];
+        let stats = StatsState::new();
+        let weather = WeatherState::new();
+
+        // Every roll comes out just under 1
+        let mut rng = StepRng::new(std::u64::MAX, 0);
+        assert!(choose_happening(&happenings, None, &stats, &weather, &mut rng).is_none());
+    }
+
+    #[test]
+    fn choose_happening_skips_unmet_conditions_and_repeats() {
+        let happenings = vec![
+            happening(0, vec![GameCondition::LensBroken]),
+            happening(1, vec![]),
+            happening(2, vec![GameCondition::Dread, GameCondition::Inspired]),
+            happening(3, vec![]),
+        ];
+        let mut stats = StatsState::new();
+        stats.set_condition(GameCondition::Dread, true);
+        let weather = WeatherState::new();
+
+        // Happening 1 ran last, and 3 is the only other one with all of its conditions met
+        let chosen = choose_happening(&happenings, Some(1), &stats, &weather, &mut always_succeeds());
+        assert_eq!(chosen.map(|happening| happening.id), Some(3));
+    }
+
+    #[test]
+    fn clicked_activity_ignores_deleted_buttons() {
+        let mut world = World::new();
+        world.register::<ActivityComponent>();
+
+        let activity = create_activities().remove(0);
+        let ent = world.create_entity().with(ActivityComponent::new(activity, 0)).build();
+        assert!(clicked_activity(&world.entities(), &world.read_storage(), ent).is_some());
+
+        // The button is deleted by a rebuild before its click is handled
+        world.delete_entity(ent).unwrap();
+        world.maintain();
+        assert!(clicked_activity(&world.entities(), &world.read_storage(), ent).is_none());
+
+        // Even once a new button takes its place
+        let activity = create_activities().remove(1);
+        world.create_entity().with(ActivityComponent::new(activity, 0)).build();
+        assert!(clicked_activity(&world.entities(), &world.read_storage(), ent).is_none());
+    }
+
+    #[test]
+    fn hotkeys_dont_restart() {
+        for key in [VirtualKeyCode::F1, VirtualKeyCode::F5, VirtualKeyCode::F12, VirtualKeyCode::Escape, VirtualKeyCode::P].iter() {
+            assert!(is_hotkey(*key));
+        }
+
+        for key in [VirtualKeyCode::Space, VirtualKeyCode::Return, VirtualKeyCode::Key1, VirtualKeyCode::A].iter() {
+            assert!(!is_hotkey(*key));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use gfx::sprite::SpriteRegion;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct AtlasRegion {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+/// Named regions of a spritesheet, loaded from a JSON sidecar file that maps names to `{ x, y, w, h }`.
+#[derive(Default)]
+pub struct SpriteAtlas {
+    regions: HashMap<String, SpriteRegion>,
+}
+
+impl SpriteAtlas {
+    pub fn load(json_path: &str) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(json_path)?;
+        let regions: HashMap<String, AtlasRegion> = serde_json::from_str(&json)?;
+
+        let regions = regions
+            .into_iter()
+            .map(|(name, r)| {
+                (
+                    name,
+                    SpriteRegion {
+                        x: r.x,
+                        y: r.y,
+                        w: r.w,
+                        h: r.h,
+                    },
+                )
+            })
+            .collect();
+
+        Ok(SpriteAtlas { regions })
+    }
+
+    pub fn get(&self, name: &str) -> Option<SpriteRegion> {
+        self.regions.get(name).cloned()
+    }
+
+    /// Get a named region. Panics if the atlas doesn't have it, since that means the atlas and code are out of sync.
+    pub fn region(&self, name: &str) -> SpriteRegion {
+        match self.get(name) {
+            Some(region) => region,
+            None => panic!("Sprite atlas has no region named {}!", name),
+        }
+    }
+}
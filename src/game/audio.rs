@@ -1,30 +1,171 @@
-use rodio::Source;
+use crate::game::{settings::Settings, stats::StatsState, GameCondition};
+use rodio::{Sink, Source};
+use specs::prelude::*;
 use std::{
     collections::HashMap,
     io::{Cursor, Read},
 };
 
-pub fn play(id: AudioAssetId, audio_db: &AudioAssetDb, is_looping: bool) {
-    let device = rodio::default_output_device().unwrap();
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SoundHandle(u32);
 
-    if let Some(clip) = audio_db.asset(&id).cloned() {
-        let s = rodio::Decoder::new(std::io::BufReader::new(Cursor::new(clip))).unwrap();
-        if is_looping {
-            rodio::play_raw(&device, s.convert_samples().repeat_infinite());
+pub struct AudioState {
+    device: Option<rodio::Device>,
+    sinks: HashMap<u32, (Sink, AudioCategory)>,
+    next_handle: u32,
+}
+
+impl AudioState {
+    pub fn new() -> Self {
+        // Open the output device once, rather than every time a sound is played
+        let device = rodio::default_output_device();
+        if device.is_none() {
+            eprintln!("Failed to find an audio output device! Audio will be disabled.");
+        }
+
+        AudioState {
+            device,
+            sinks: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Play a sound at the volume the settings give its category.
+    pub fn play(
+        &mut self,
+        id: AudioAssetId,
+        audio_db: &AudioAssetDb,
+        settings: &Settings,
+        is_looping: bool,
+    ) -> Option<SoundHandle> {
+        let device = self.device.as_ref()?;
+
+        if let Some(clip) = audio_db.asset(&id).cloned() {
+            let s = rodio::Decoder::new(std::io::BufReader::new(Cursor::new(clip))).unwrap();
+            let s = s.convert_samples::<f32>();
+
+            let category = id.category();
+            let sink = Sink::new(device);
+            sink.set_volume(settings.volume(category));
+            if is_looping {
+                sink.append(s.repeat_infinite());
+            } else {
+                sink.append(s);
+            }
+
+            let handle = SoundHandle(self.next_handle);
+            self.next_handle += 1;
+            self.sinks.insert(handle.0, (sink, category));
+
+            Some(handle)
         } else {
-            rodio::play_raw(&device, s.convert_samples());
+            eprintln!(
+                "Failed to play audio file! Audio asset with id {:?} did not exist!",
+                id
+            );
+
+            None
+        }
+    }
+
+    pub fn is_playing(&self, handle: SoundHandle) -> bool {
+        self.sinks.contains_key(&handle.0)
+    }
+
+    pub fn stop(&mut self, handle: SoundHandle) {
+        if let Some((sink, _)) = self.sinks.remove(&handle.0) {
+            sink.stop();
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        for (_, (sink, _)) in self.sinks.drain() {
+            sink.stop();
+        }
+    }
+
+    /// Update the volume of sounds that are already playing, after the settings change.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        for (sink, category) in self.sinks.values() {
+            sink.set_volume(settings.volume(*category));
+        }
+    }
+
+    /// Drop any sinks that have finished playing their sounds.
+    fn prune_finished(&mut self) {
+        self.sinks.retain(|_, (sink, _)| !sink.empty());
+    }
+}
+
+// Loops that play for as long as a condition is set
+const AMBIENT_LOOPS: [(GameCondition, AudioAssetId); 2] = [
+    (GameCondition::Insane, AudioAssetId::Whispers),
+    (GameCondition::Dread, AudioAssetId::Heartbeat),
+];
+
+#[derive(Default)]
+pub struct AudioSystem {
+    // Whether the music has been started. It plays for the whole game, and isn't retried if it failed to play.
+    is_music_started: bool,
+    // The ambient loops that have been started, by the condition that started them.
+    // The handle is None if the loop failed to play, so that it isn't retried every tick.
+    ambient_loops: HashMap<GameCondition, Option<SoundHandle>>,
+}
+
+impl<'a> System<'a> for AudioSystem {
+    type SystemData = (
+        WriteExpect<'a, AudioState>,
+        ReadExpect<'a, AudioAssetDb>,
+        ReadExpect<'a, StatsState>,
+        ReadExpect<'a, Settings>,
+    );
+
+    fn run(&mut self, (mut audio, audio_db, stats, settings): Self::SystemData) {
+        audio.prune_finished();
+
+        if !self.is_music_started {
+            audio.play(AudioAssetId::Theme, &audio_db, &settings, true);
+            self.is_music_started = true;
+        }
+
+        for (condition, id) in AMBIENT_LOOPS.iter() {
+            let is_active = stats.condition(*condition);
+            let is_started = self.ambient_loops.contains_key(condition);
+
+            if is_active && !is_started {
+                let handle = audio.play(*id, &audio_db, &settings, true);
+                self.ambient_loops.insert(*condition, handle);
+            } else if !is_active && is_started {
+                if let Some(Some(handle)) = self.ambient_loops.remove(condition) {
+                    audio.stop(handle);
+                }
+            }
         }
-    } else {
-        eprintln!(
-            "Failed to play audio file! Audio asset with id {:?} did not exist!",
-            id
-        );
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum AudioAssetId {
-    // TODO...
+    Theme,
+    Whispers,
+    Heartbeat,
+}
+
+impl AudioAssetId {
+    pub fn category(&self) -> AudioCategory {
+        match *self {
+            AudioAssetId::Theme => AudioCategory::Music,
+            // The whispers and heartbeat react to the player's sanity, so they're effects rather than music
+            AudioAssetId::Whispers | AudioAssetId::Heartbeat => AudioCategory::Sfx,
+        }
+    }
+}
+
+/// Which volume setting a sound follows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AudioCategory {
+    Music,
+    Sfx,
 }
 
 pub struct AudioAssetDb {
@@ -39,7 +180,7 @@ impl AudioAssetDb {
     }
 
     pub fn import(&mut self, id: AudioAssetId, path: &str) -> std::io::Result<()> {
-        let mut file = std::fs::File::open(path).unwrap();
+        let mut file = std::fs::File::open(path)?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
 
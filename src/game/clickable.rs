@@ -7,6 +7,13 @@ pub struct OnClickedEvent {
     pub ent: Entity,
 }
 
+/// What the pointer is over, as of the last tick.
+#[derive(Default)]
+pub struct PointerState {
+    pub over_clickable: bool,
+    pub hovered: Option<Entity>,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum ClickableState {
     Normal,
@@ -15,13 +22,35 @@ enum ClickableState {
 }
 
 pub struct ClickableComponent {
+    // Disabled clickables can still be hovered, but can't be clicked
+    pub disabled: bool,
     state: ClickableState,
+    normal_sprite: Option<SpriteRegion>,
+    hovered_sprite: Option<SpriteRegion>,
+    clicked_sprite: Option<SpriteRegion>,
 }
 
 impl ClickableComponent {
     pub fn new() -> Self {
         ClickableComponent {
+            disabled: false,
             state: ClickableState::Normal,
+            normal_sprite: None,
+            hovered_sprite: None,
+            clicked_sprite: None,
+        }
+    }
+
+    /// Swap the entity's sprite region while hovered or clicked.
+    /// The original region is used for any state that doesn't have one.
+    pub fn with_sprites(
+        hovered_sprite: Option<SpriteRegion>,
+        clicked_sprite: Option<SpriteRegion>,
+    ) -> Self {
+        ClickableComponent {
+            hovered_sprite,
+            clicked_sprite,
+            ..ClickableComponent::new()
         }
     }
 }
@@ -30,6 +59,51 @@ impl Component for ClickableComponent {
     type Storage = VecStorage<Self>;
 }
 
+/// Start building a clickable UI button, `size_px` big in screen space.
+/// The sprite is scaled to fill that size, and the collider is sized to match it, so the two can't disagree.
+/// Disabled buttons are grayed out. Add a component to the returned builder to say what clicking the button does.
+pub fn spawn_button(
+    world: &mut World,
+    pos: Vector2d,
+    size_px: Vector2d,
+    sprite_region: SpriteRegion,
+    hovered_sprite_region: Option<SpriteRegion>,
+    disabled: bool,
+) -> EntityBuilder {
+    let scale = Vector2f::new(
+        size_px.x as f32 / sprite_region.w as f32,
+        size_px.y as f32 / sprite_region.h as f32,
+    );
+
+    let mut clickable = ClickableComponent::with_sprites(hovered_sprite_region, None);
+    clickable.disabled = disabled;
+
+    let mut sprite = SpriteComponent::new(
+        sprite_region,
+        resources::TEX_SPRITESHEET_UI,
+        Point2f::origin(),
+        COLOR_WHITE,
+        layers::Layer::Buttons,
+        Transparency::Opaque,
+    );
+
+    if disabled {
+        sprite.tint = Color::rgba_f32(0.6, 0.6, 0.6, 1.0);
+    }
+
+    world
+        .create_entity()
+        .with(TransformComponent::new(pos, scale))
+        .with(ColliderComponent::new(
+            Cuboid::new(screen_to_world(size_px / 2.0)),
+            Vector2d::zeros(),
+            CollisionGroups::new(),
+            0.0,
+        ))
+        .with(clickable)
+        .with(sprite)
+}
+
 #[derive(Default)]
 pub struct ClickableSystem;
 
@@ -38,8 +112,11 @@ impl<'a> System<'a> for ClickableSystem {
         Entities<'a>,
         ReadExpect<'a, InputState>,
         ReadExpect<'a, PhysicsState>,
+        ReadExpect<'a, ScreenState>,
+        WriteExpect<'a, PointerState>,
         WriteExpect<'a, EventChannel<OnClickedEvent>>,
         WriteStorage<'a, ClickableComponent>,
+        WriteStorage<'a, SpriteComponent>,
     );
 
     fn setup(&mut self, world: &mut World) {
@@ -48,36 +125,37 @@ impl<'a> System<'a> for ClickableSystem {
 
     fn run(
         &mut self,
-        (ents, input, physics, mut on_clicked_events, mut clickables): Self::SystemData,
+        (ents, input, physics, screen, mut pointer, mut on_clicked_events, mut clickables, mut sprites): Self::SystemData,
     ) {
-        // Gather all ents hit by the mouse
+        // Gather all ents hit by the mouse.
+        // Only colliders that belong to an entity can be clicked, so any others are skipped.
         let mut cursor_hit_ents = BitSet::new();
-        let mouse_pos_world = input.cursor_pos() * PIXELS_TO_WORLD_UNITS;
+        let mouse_pos_world =
+            screen_to_world(input.cursor_pos_logical(screen.dpi_scale_factor, screen.render_scale));
         let all_collision_groups = CollisionGroups::new();
 
         for interference in
             physics.interferences_with_point(&mouse_pos_world, &all_collision_groups)
         {
-            let hit_ent = interference
-                .1
-                .user_data()
-                .unwrap()
-                .downcast_ref::<Entity>()
-                .cloned()
-                .unwrap();
-            cursor_hit_ents.add(hit_ent.id());
+            if let Some(hit_ent) = collider_entity(interference.1) {
+                cursor_hit_ents.add(hit_ent.id());
+            }
         }
 
-        // How do we change the sprite state?
-        // Could just grab the sprite components
-        // new field on clickable component
-        // hovered_sprite: Option<SpriteRegion>
-        // clicked_sprite: Option<SpriteRegion>
-        // at the end of the loop, set the sprite based on the ClickableState
-        // (if normal_sprite is none, just set it to whatever the sprite is right now)
+        pointer.over_clickable = false;
+        pointer.hovered = None;
 
         for (ent, clickable) in (&ents, &mut clickables).join() {
             if cursor_hit_ents.contains(ent.id()) {
+                pointer.hovered = Some(ent);
+
+                if clickable.disabled {
+                    clickable.state = ClickableState::Normal;
+                    continue;
+                }
+
+                pointer.over_clickable = true;
+
                 if input.is_mouse_button_pressed(MouseButton::Left) {
                     if clickable.state != ClickableState::Clicked {
                         //println!("click down");
@@ -102,5 +180,33 @@ impl<'a> System<'a> for ClickableSystem {
                 }
             }
         }
+
+        // Set the sprite based on the ClickableState.
+        // Only write sprites whose region changed, so they aren't flagged as modified every tick.
+        let mut sprite_changes: Vec<(Entity, SpriteRegion)> = Vec::new();
+        for (ent, clickable, sprite) in (&ents, &mut clickables, &sprites).join() {
+            // The first time we see this sprite, remember its region as the normal one
+            let normal_sprite = *clickable.normal_sprite.get_or_insert(sprite.region);
+
+            let region = match clickable.state {
+                ClickableState::Normal => normal_sprite,
+                ClickableState::Hovered => clickable.hovered_sprite.unwrap_or(normal_sprite),
+                ClickableState::Clicked => clickable.clicked_sprite.unwrap_or(normal_sprite),
+            };
+
+            if sprite.region != region {
+                sprite_changes.push((ent, region));
+            }
+        }
+
+        for (ent, region) in sprite_changes {
+            if let Some(sprite) = sprites.get_mut(ent) {
+                sprite.region = region;
+                sprite.pivot_pixels = Point2f::new(
+                    sprite.pivot.x * region.w as f32,
+                    sprite.pivot.y * region.h as f32,
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,60 @@
+//! Conversions between screen space and world space.
+//!
+//! Screen space is in render units, which are pixels at a render scale of 1. The origin is the top left of
+//! the window, and y grows downwards. Transforms, sprites, and the cursor (from `InputState::cursor_pos_logical`)
+//! are all in screen space. The renderer's projection is built with 0 at the bottom and the height at the top,
+//! but Vulkan's clip space has y pointing down, so the two cancel out and y = 0 really is the top of the screen.
+//!
+//! World space is what the physics world uses. It has the same origin and axes as screen space, scaled down by
+//! `PIXELS_PER_WORLD_UNIT`, so converting is only ever a scale and never a flip.
+//!
+//! Both functions work on points, vectors, and sizes alike. Everything that crosses between the two spaces should go
+//! through them, so the scale only ever lives here.
+
+use std::ops::Mul;
+
+/// How many render units make up one physics unit. This is the only scale to change when tuning collision sizes.
+pub const PIXELS_PER_WORLD_UNIT: u32 = 32;
+
+/// The inverse of `PIXELS_PER_WORLD_UNIT`. It's always derived from it, so that
+/// `PIXELS_TO_WORLD_UNITS == 1.0 / PIXELS_PER_WORLD_UNIT` holds and the two conversions can't drift apart.
+const PIXELS_TO_WORLD_UNITS: f64 = 1.0 / PIXELS_PER_WORLD_UNIT as f64;
+
+/// Convert from screen space (render units) to world space (physics units).
+pub fn screen_to_world<T: Mul<f64, Output = T>>(screen: T) -> T {
+    screen * PIXELS_TO_WORLD_UNITS
+}
+
+/// Convert from world space (physics units) to screen space (render units).
+pub fn world_to_screen<T: Mul<f64, Output = T>>(world: T) -> T {
+    world * PIXELS_PER_WORLD_UNIT as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Point2d, Vector2d};
+
+    #[test]
+    fn one_world_unit_is_32_pixels() {
+        assert_eq!(screen_to_world(32.0), 1.0);
+        assert_eq!(world_to_screen(1.0), 32.0);
+    }
+
+    #[test]
+    fn converts_points_and_vectors() {
+        assert_eq!(screen_to_world(Point2d::new(64.0, -16.0)), Point2d::new(2.0, -0.5));
+        assert_eq!(world_to_screen(Point2d::new(2.0, -0.5)), Point2d::new(64.0, -16.0));
+        assert_eq!(screen_to_world(Vector2d::new(8.0, 96.0)), Vector2d::new(0.25, 3.0));
+        assert_eq!(world_to_screen(Vector2d::new(0.25, 3.0)), Vector2d::new(8.0, 96.0));
+    }
+
+    #[test]
+    fn round_trip() {
+        let screen = Point2d::new(123.0, 456.5);
+        assert_eq!(world_to_screen(screen_to_world(screen)), screen);
+
+        let world = Vector2d::new(1.0, 7.25);
+        assert_eq!(screen_to_world(world_to_screen(world)), world);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::game::*;
+use gfx::window::DeltaTime;
+use specs::prelude::*;
+
+// How long a floater lasts, in seconds
+const FLOATER_LIFETIME: f64 = 1.2;
+// How fast a floater rises, in pixels per second
+const FLOATER_RISE_SPEED: f64 = 30.0;
+
+/// Text that rises from a stat and fades out, like "+1" by the food icon after catching a fish.
+pub struct Floater {
+    pub position: (f32, f32),
+    pub text: String,
+    pub color: Color,
+    // Seconds until the floater goes away
+    pub lifetime: f64,
+}
+
+#[derive(Default)]
+pub struct FloaterState {
+    pub floaters: Vec<Floater>,
+}
+
+impl FloaterState {
+    /// Show the change in a stat next to its icon.
+    pub fn spawn(&mut self, stat: Stat, amount: i32, screen_width: u32) {
+        let (x, y) = stat_icon_position(stat, screen_width);
+
+        // The icons are on the right edge of the screen, so those floaters go to their left.
+        // Money is on the left edge, so its floater goes to the right of the text.
+        let position = match stat {
+            Stat::Money => (x + 8.0, y + 8.0),
+            _ => (x - 24.0, y + 16.0),
+        };
+
+        let (text, color) = if amount < 0 {
+            (format!("-{}", amount.abs()), COLOR_RED)
+        } else {
+            (format!("+{}", amount), COLOR_GREEN)
+        };
+
+        self.floaters.push(Floater {
+            position,
+            text,
+            color,
+            lifetime: FLOATER_LIFETIME,
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct FloaterSystem {
+    game_event_reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for FloaterSystem {
+    type SystemData = (
+        Write<'a, RenderState>,
+        WriteExpect<'a, FloaterState>,
+        ReadExpect<'a, EventChannel<GameEvent>>,
+        ReadExpect<'a, ScreenState>,
+        ReadExpect<'a, DeltaTime>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+
+        self.game_event_reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (mut render, mut floater_state, game_events, screen, dt): Self::SystemData) {
+        let mut render = render.scope();
+
+        for event in game_events.read(&mut self.game_event_reader.as_mut().unwrap()) {
+            match event {
+                GameEvent::NewGameStarted => {
+                    floater_state.floaters.clear();
+                }
+                GameEvent::HandleStatEffects { effects } => {
+                    // One floater per stat, with the net change, the same as the log summary
+                    let mut changes: Vec<(Stat, i32)> = Vec::new();
+                    for effect in effects {
+                        let (stat, amount) = match effect {
+                            StatEffect::Add { stat, amount } => (*stat, *amount),
+                            StatEffect::Subtract { stat, amount } => (*stat, -*amount),
+                        };
+
+                        match changes.iter_mut().find(|(s, _)| *s == stat) {
+                            Some((_, net)) => *net += amount,
+                            None => changes.push((stat, amount)),
+                        }
+                    }
+
+                    for (stat, net) in changes {
+                        if net != 0 {
+                            floater_state.spawn(stat, net, screen.width);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for floater in floater_state.floaters.iter_mut() {
+            floater.lifetime -= *dt;
+            floater.position.1 -= (FLOATER_RISE_SPEED * *dt) as f32;
+        }
+        floater_state.floaters.retain(|floater| floater.lifetime > 0.0);
+
+        render.bind_transparency(Transparency::Transparent);
+        render.bind_layer(layers::Layer::Ui);
+        render.bind_texture(resources::TEX_FONT);
+
+        for floater in floater_state.floaters.iter() {
+            let alpha = (floater.lifetime / FLOATER_LIFETIME) as f32;
+            render.bind_color(floater.color.with_alpha(alpha));
+            render.text(floater.position.0, floater.position.1, 8, 16, 1.0, &floater.text);
+        }
+    }
+}
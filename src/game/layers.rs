@@ -1,8 +1,33 @@
-pub type Layer = u8;
+/// Draw order of everything in the scene. Later layers are drawn on top of earlier ones.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    Background,
+    // The lighthouse beam, just above the background
+    Beam,
+    Lab,
+    Glass,
+    Buttons,
+    Ui,
+    // Drawn over the UI, like the pause text and volume controls over the pause overlay
+    Overlay,
+    Tooltip,
+    Debug,
+}
 
-pub const LAYER_BG: Layer = 0;
-pub const LAYER_LAB: Layer = 1;
-pub const LAYER_BG_GLASS: Layer = 2;
-pub const LAYER_BG_WORKSTATION: Layer = 4;
-pub const LAYER_BUTTONS: Layer = 5;
-pub const LAYER_UI: Layer = 10;
+impl Layer {
+    /// The value used for this layer in render keys.
+    pub fn value(self) -> u8 {
+        match self {
+            Layer::Background => 0,
+            // Shares the lab's value, but the beam is additive, and additive batches are drawn after everything else
+            Layer::Beam => 1,
+            Layer::Lab => 1,
+            Layer::Glass => 2,
+            Layer::Buttons => 5,
+            Layer::Ui => 10,
+            Layer::Overlay => 11,
+            Layer::Tooltip => 15,
+            Layer::Debug => 20,
+        }
+    }
+}
@@ -1,18 +1,47 @@
 use crate::game::{physics::*, Point2d, *};
-use gfx::input::*;
+use gfx::{input::*, window::DeltaTime};
 use ncollide2d::pipeline::CollisionGroups;
 use rand::Rng;
 use specs::prelude::*;
+use std::collections::VecDeque;
+
+const LOG_MAX_LINES: usize = 20;
+// The full history is only capped so a very long session can't grow it forever
+const LOG_HISTORY_MAX_LINES: usize = 10000;
+const LOG_WRAP_WIDTH: f32 = 624.0;
+const LOG_GLYPH_W: u32 = 8;
+const LOG_GLYPH_H: u32 = 16;
+const LOG_TEXT_SCALE: f32 = 1.0;
+const LOG_REVEAL_CHARS_PER_SECOND: f64 = 40.0;
 
 #[derive(Clone)]
 pub struct LogEvent {
     pub message: String,
     pub color: Color,
+    // Reveal the message one character at a time, rather than all at once
+    pub reveal: bool,
 }
 
 #[derive(Default)]
 pub struct LogState {
     pub logs: Vec<LogEvent>,
+    // Seconds since the newest log arrived
+    pub newest_log_time: f64,
+    // Every message logged this session, oldest first, prefixed with the day and time it happened
+    pub history: VecDeque<String>,
+}
+
+impl LogState {
+    /// Write the full log history to a text file, one message per line.
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        let mut text = String::new();
+        for line in self.history.iter() {
+            text.push_str(line);
+            text.push('\n');
+        }
+
+        std::fs::write(path, text)
+    }
 }
 
 #[derive(Default)]
@@ -25,6 +54,8 @@ impl<'a> System<'a> for LogSystem {
         WriteExpect<'a, RenderState>,
         WriteExpect<'a, LogState>,
         ReadExpect<'a, EventChannel<LogEvent>>,
+        ReadExpect<'a, DeltaTime>,
+        ReadExpect<'a, TimeState>,
     );
 
     fn setup(&mut self, world: &mut World) {
@@ -37,26 +68,76 @@ impl<'a> System<'a> for LogSystem {
         );
     }
 
-    fn run(&mut self, (mut render, mut log_state, log_events): Self::SystemData) {
+    fn run(&mut self, (mut render, mut log_state, log_events, dt, time): Self::SystemData) {
+        let mut render = render.scope();
+
+        log_state.newest_log_time += *dt;
+
+        // Bound before wrapping, since wrapping measures the text with the bound font
+        render.bind_transparency(Transparency::Transparent);
+        render.bind_layer(layers::Layer::Ui);
+        render.bind_texture(resources::TEX_FONT);
+
         for event in log_events.read(&mut self.log_event_reader.as_mut().unwrap()) {
-           log_state.logs.insert(0, event.clone());
+            log_state.history.push_back(format!(
+                "[Day {}, {}] {}",
+                time.day,
+                clock_label(time.time_of_day, time.hours_passed),
+                event.message
+            ));
+            if log_state.history.len() > LOG_HISTORY_MAX_LINES {
+                log_state.history.pop_front();
+            }
+
+            // Only the newest log is revealed, so this finishes any reveal that was in progress
+            log_state.logs.insert(0, event.clone());
+            log_state.newest_log_time = 0.0;
+
+            // Wrapped lines count toward the cap, so drop the oldest logs until everything fits
+            while log_state.logs.len() > 1 {
+                let line_count: usize = log_state.logs.iter().map(|log| wrap_log(&render, log).len()).sum();
+                if line_count <= LOG_MAX_LINES {
+                    break;
+                }
 
-           if log_state.logs.len() > 20 {
-               log_state.logs.pop();
-           }
+                log_state.logs.pop();
+            }
         }
 
         let pos_x = 640.0;
         let pos_y = 700.0;
-        render.bind_transparency(Transparency::Opaque);
-        render.bind_layer(layers::LAYER_UI);
-        render.bind_texture(resources::TEX_FONT);
+
+        // The newest log is at the bottom, with each log's lines drawn top to bottom
+        let mut line_idx: usize = 0;
         for (i, log) in log_state.logs.iter().enumerate() {
-            let color_lerp_percent = i as f32 / 32.0;
-            let color = color_lerp(log.color, COLOR_WHITE, color_lerp_percent);
-            render.bind_color(color);
+            // Older logs fade out
+            let fade_percent = i as f32 / 32.0;
+            render.bind_color(color_lerp(log.color, log.color.with_alpha(0.0), fade_percent));
 
-            render.text(pos_x, pos_y - (i as f32 * 16.0), 8, 16, 1.0, &log.message);
+            // Wrap the full message, so that lines don't reflow as it's revealed
+            let lines = wrap_log(&render, log);
+            let mut chars_remaining = if i == 0 && log.reveal {
+                (log_state.newest_log_time * LOG_REVEAL_CHARS_PER_SECOND) as usize
+            } else {
+                std::usize::MAX
+            };
+
+            line_idx += lines.len();
+            for (j, line) in lines.iter().enumerate() {
+                // Reveal by characters, and cut at a character boundary so non-ascii text can't split a character
+                let visible_chars = line.chars().count().min(chars_remaining);
+                chars_remaining -= visible_chars;
+                let visible_len = line.char_indices().nth(visible_chars).map_or(line.len(), |(i, _)| i);
+
+                let y = pos_y - ((line_idx - 1 - j) as f32 * LOG_GLYPH_H as f32);
+                render.text(pos_x, y, LOG_GLYPH_W, LOG_GLYPH_H, LOG_TEXT_SCALE, &line[..visible_len]);
+            }
         }
     }
 }
+
+fn wrap_log(render: &RenderState, log: &LogEvent) -> Vec<String> {
+    render::wrap_text(&log.message, LOG_WRAP_WIDTH, |text| {
+        render.measure_text(text, LOG_GLYPH_W, LOG_GLYPH_H, LOG_TEXT_SCALE).0
+    })
+}
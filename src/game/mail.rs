@@ -0,0 +1,216 @@
+use crate::game::{rng::GameRng, *};
+use rand::{seq::SliceRandom, Rng};
+use specs::prelude::*;
+
+#[derive(Clone)]
+pub struct Letter {
+    pub id: i32,
+    pub chance: f32,
+    pub stat_effects: Vec<StatEffect>,
+    pub message: String,
+}
+
+// Marks the mail button, which opens the delivered letter when clicked
+#[derive(Default)]
+pub struct MailComponent;
+
+impl Component for MailComponent {
+    type Storage = NullStorage<Self>;
+}
+
+#[derive(Default)]
+pub struct MailState {
+    pub letters: Vec<Letter>,
+    pub delivered_letter: Option<Letter>,
+}
+
+impl MailState {
+    pub fn new() -> Self {
+        MailState {
+            letters: create_letters(),
+            delivered_letter: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MailSystem {
+    game_event_reader: Option<ReaderId<GameEvent>>,
+    on_clicked_event_reader: Option<ReaderId<OnClickedEvent>>,
+}
+
+impl<'a> System<'a> for MailSystem {
+    type SystemData = (
+        WriteExpect<'a, MailState>,
+        WriteExpect<'a, GameRng>,
+        ReadExpect<'a, EventChannel<OnClickedEvent>>,
+        WriteExpect<'a, EventChannel<GameEvent>>,
+        WriteExpect<'a, EventChannel<LogEvent>>,
+        ReadStorage<'a, MailComponent>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+
+        self.game_event_reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+
+        self.on_clicked_event_reader = Some(
+            world
+                .fetch_mut::<EventChannel<OnClickedEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(
+        &mut self,
+        (mut mail_state, mut rng, on_clicked_events, mut game_events, mut log_events, mail_comps): Self::SystemData,
+    ) {
+        let mut delivered_letter: Option<Letter> = None;
+        for event in game_events.read(&mut self.game_event_reader.as_mut().unwrap()) {
+            match event {
+                GameEvent::NewGameStarted => {
+                    *mail_state = MailState::new();
+                }
+                GameEvent::NewDayStarted { .. } => {
+                    // Only one letter can be waiting at a time
+                    if mail_state.delivered_letter.is_some() {
+                        continue;
+                    }
+
+                    let mut letters = mail_state.letters.clone();
+                    letters.shuffle(&mut *rng);
+                    for letter in letters {
+                        let roll: f32 = rng.gen();
+                        if roll < letter.chance {
+                            delivered_letter = Some(letter);
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(letter) = delivered_letter {
+            log_events.single_write(LogEvent { message: String::from("A letter has arrived with the morning tide."), color: COLOR_BLACK, reveal: false });
+            mail_state.delivered_letter = Some(letter);
+
+            // Rebuild the entities so the mail button shows up
+            game_events.single_write(GameEvent::RefreshActivities);
+        }
+
+        for event in on_clicked_events.read(&mut self.on_clicked_event_reader.as_mut().unwrap()) {
+            if !mail_comps.contains(event.ent) {
+                continue;
+            }
+
+            if let Some(letter) = mail_state.delivered_letter.take() {
+                log_events.single_write(LogEvent { message: letter.message, color: COLOR_BLUE, reveal: true });
+                game_events.single_write(GameEvent::HandleStatEffects {
+                    effects: letter.stat_effects,
+                });
+
+                // Rebuild the entities so the mail button goes away
+                game_events.single_write(GameEvent::RefreshActivities);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MailInfoRenderSystem;
+
+impl<'a> System<'a> for MailInfoRenderSystem {
+    type SystemData = (
+        Write<'a, RenderState>,
+        ReadExpect<'a, MailState>,
+        ReadStorage<'a, MailComponent>,
+        ReadStorage<'a, TransformComponent>,
+    );
+
+    fn run(&mut self, (mut render, mail_state, mail_comps, transforms): Self::SystemData) {
+        let mut render = render.scope();
+
+        if mail_state.delivered_letter.is_none() {
+            return;
+        }
+
+        render.bind_transparency(Transparency::Opaque);
+        render.bind_texture(resources::TEX_FONT);
+        render.bind_color(COLOR_BLACK);
+        render.bind_layer(layers::Layer::Ui);
+
+        for (_, transform) in (&mail_comps, &transforms).join() {
+            let x = transform.position.x as f32 + 16.0;
+            let y = transform.position.y as f32 + 16.0;
+
+            render.text(x, y, 8, 16, 1.2, "Mail");
+            render.text(x, y + 20.0, 8, 16, 1.0, "Read the letter");
+        }
+    }
+}
+
+/// Create the mail button for the delivered letter, if there is one.
+/// It uses the same sprite as the activity buttons, and its text says what it's for.
+pub fn create_mail_ents(world: &mut World) {
+    if world.read_resource::<MailState>().delivered_letter.is_none() {
+        return;
+    }
+
+    let button_sprite_region = SpriteRegion {
+        x: 0,
+        y: 160,
+        w: 160,
+        h: 96,
+    };
+    let button_hovered_sprite_region = SpriteRegion {
+        x: 160,
+        y: 160,
+        w: 160,
+        h: 96,
+    };
+
+    spawn_button(
+        world,
+        Vector2d::new(16.0, 130.0),
+        Vector2d::new(160.0, 96.0),
+        button_sprite_region,
+        Some(button_hovered_sprite_region),
+        false,
+    )
+    .with(MailComponent)
+    .build();
+}
+
+pub fn create_letters() -> Vec<Letter> {
+    vec![
+        Letter {
+            id: 0,
+            message: String::from("A letter from your sister. Everyone at home misses you dearly. (Sanity +2)"),
+            chance: 0.15,
+            stat_effects: vec![StatEffect::Add { stat: Stat::Sanity, amount: 2 }],
+        },
+        Letter {
+            id: 1,
+            message: String::from("The lighthouse authority reminds you to keep the light burning. Some spare parts are tucked inside. (Parts +1)"),
+            chance: 0.1,
+            stat_effects: vec![StatEffect::Add { stat: Stat::Parts, amount: 1 }],
+        },
+        Letter {
+            id: 2,
+            message: String::from("An old friend writes to wish you well, with a few dollars folded inside. (Money +3)"),
+            chance: 0.1,
+            stat_effects: vec![StatEffect::Add { stat: Stat::Money, amount: 3 }],
+        },
+        Letter {
+            id: 3,
+            message: String::from("A water stained letter with no return address. The handwriting is your own. (Sanity -1)"),
+            chance: 0.05,
+            stat_effects: vec![StatEffect::Subtract { stat: Stat::Sanity, amount: 1 }],
+        },
+    ]
+}
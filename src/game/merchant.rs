@@ -1,13 +1,14 @@
-use crate::game::{physics::*, Point2d, *};
+use crate::game::{physics::*, rng::GameRng, Point2d, *};
 use gfx::input::*;
 use ncollide2d::pipeline::CollisionGroups;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
 const MIN_MERCHANT_ARRIVAL_DAYS: i32 = 4;
 const MAX_MERCHANT_ARRIVAL_DAYS: i32 = 7;
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct MerchantState {
     has_arrived: bool,
     next_arrival_day: i32,
@@ -17,9 +18,9 @@ pub struct MerchantState {
 }
 
 impl MerchantState {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut GameRng) -> Self {
         let next_arrival_day =
-            rand::thread_rng().gen_range(MIN_MERCHANT_ARRIVAL_DAYS, MAX_MERCHANT_ARRIVAL_DAYS);
+            rng.gen_range(MIN_MERCHANT_ARRIVAL_DAYS, MAX_MERCHANT_ARRIVAL_DAYS);
         MerchantState {
             has_arrived: false,
             next_arrival_day,
@@ -28,6 +29,10 @@ impl MerchantState {
             part_price: 4,
         }
     }
+
+    pub fn has_arrived(&self) -> bool {
+        self.has_arrived
+    }
 }
 
 #[derive(Default)]
@@ -38,9 +43,10 @@ pub struct MerchantSystem {
 impl<'a> System<'a> for MerchantSystem {
     type SystemData = (
         WriteExpect<'a, RenderState>,
-        WriteExpect<'a, StatsState>,
+        ReadExpect<'a, StatsState>,
         ReadExpect<'a, InputState>,
         WriteExpect<'a, MerchantState>,
+        WriteExpect<'a, GameRng>,
         WriteExpect<'a, EventChannel<GameEvent>>,
         WriteExpect<'a, EventChannel<LogEvent>>,
     );
@@ -55,26 +61,30 @@ impl<'a> System<'a> for MerchantSystem {
         );
     }
 
-    fn run(&mut self, (mut render, mut stats, input, mut merchant_state, mut game_events, mut log_events): Self::SystemData) {
+    fn run(&mut self, (mut render, stats, input, mut merchant_state, mut rng, mut game_events, mut log_events): Self::SystemData) {
+        let mut render = render.scope();
+
         for event in game_events.read(&mut self.game_event_reader.as_mut().unwrap()) {
             match event {
+                GameEvent::NewGameStarted => {
+                    *merchant_state = MerchantState::new(&mut rng);
+                }
                 GameEvent::NewDayStarted { day } => {
                     if *day >= merchant_state.next_arrival_day {
                         merchant_state.has_arrived = true;
-                        merchant_state.next_arrival_day = day + rand::thread_rng()
+                        merchant_state.next_arrival_day = day + rng
                         .gen_range(MIN_MERCHANT_ARRIVAL_DAYS, MAX_MERCHANT_ARRIVAL_DAYS);
 
-                        log_events.single_write(LogEvent { message: String::from("A merchant ship arrives, looking to sell some basic goods."), color: COLOR_YELLOW });
+                        log_events.single_write(LogEvent { message: String::from("A merchant ship arrives, looking to sell some basic goods."), color: COLOR_YELLOW, reveal: false });
                     }
                 }
                 GameEvent::NewTimeOfDayStarted { time_of_day } => {
                     if merchant_state.has_arrived && (*time_of_day == TimeOfDay::Night) {
-                        log_events.single_write(LogEvent { message: String::from("The merchant ship sails off into the sunset."), color: COLOR_YELLOW });
+                        log_events.single_write(LogEvent { message: String::from("The merchant ship sails off into the sunset."), color: COLOR_YELLOW, reveal: false });
 
-                        let mut rand = rand::thread_rng();
-                        merchant_state.food_price = rand.gen_range(2, 4);
-                        merchant_state.gas_price = rand.gen_range(3, 8);
-                        merchant_state.part_price = rand.gen_range(3, 10);
+                        merchant_state.food_price = rng.gen_range(2, 4);
+                        merchant_state.gas_price = rng.gen_range(3, 8);
+                        merchant_state.part_price = rng.gen_range(3, 10);
 
                         merchant_state.has_arrived = false;
                     }
@@ -95,18 +105,19 @@ impl<'a> System<'a> for MerchantSystem {
             render.bind_transparency(Transparency::Opaque);
             render.bind_texture(resources::TEX_SPRITESHEET_UI);
             render.bind_color(COLOR_WHITE);
-            render.bind_layer(layers::LAYER_UI);
-            render.sprite(
+            render.bind_layer(layers::Layer::Ui);
+            render.nine_slice(
                 pos_x,
                 pos_y,
-                Point2f::new(0.5, 0.5),
-                Vector2f::new(2.0, 2.0),
+                320.0,
+                192.0,
                 SpriteRegion {
                     x: 0,
                     y: 160,
                     w: 160,
                     h: 96,
                 },
+                8,
             );
 
             // Render text and prices for shop items
@@ -147,39 +158,42 @@ impl<'a> System<'a> for MerchantSystem {
                 "(Use keyboard)",
             );
 
-            // Handle purchases
-            let mut did_purchase = false;
+            // Handle purchases. The stat changes go through HandleStatEffects, so they're logged and shown like any other.
+            let mut purchase: Option<(Stat, i32)> = None;
             let current_money = stats.stat(Stat::Money);
             if input.is_key_pressed(VirtualKeyCode::Key1) {
                 if current_money >= merchant_state.food_price {
-                    stats.add(Stat::Money, -merchant_state.food_price);
-                    stats.add(Stat::Food, 1);
-                    did_purchase = true;
-                    log_events.single_write(LogEvent { message: String::from("You purchase some food."), color: COLOR_GREEN });
+                    purchase = Some((Stat::Food, merchant_state.food_price));
+                    log_events.single_write(LogEvent { message: String::from("You purchase some food."), color: COLOR_GREEN, reveal: false });
                 } else {
-                    log_events.single_write(LogEvent { message: String::from("You don't have enough money for that..."), color: COLOR_RED });
+                    log_events.single_write(LogEvent { message: String::from("You don't have enough money for that..."), color: COLOR_RED, reveal: false });
                 }
             } else if input.is_key_pressed(VirtualKeyCode::Key2) {
                 if current_money >= merchant_state.gas_price {
-                    stats.add(Stat::Money, -merchant_state.gas_price);
-                    stats.add(Stat::Gas, 1);
-                    did_purchase = true;
-                    log_events.single_write(LogEvent { message: String::from("You purchase some gas."), color: COLOR_GREEN });
+                    purchase = Some((Stat::Gas, merchant_state.gas_price));
+                    log_events.single_write(LogEvent { message: String::from("You purchase some gas."), color: COLOR_GREEN, reveal: false });
                 } else {
-                    log_events.single_write(LogEvent { message: String::from("You don't have enough money for that..."), color: COLOR_RED });
+                    log_events.single_write(LogEvent { message: String::from("You don't have enough money for that..."), color: COLOR_RED, reveal: false });
                 }
             } else if input.is_key_pressed(VirtualKeyCode::Key3) {
                 if current_money >= merchant_state.part_price {
-                    stats.add(Stat::Money, -merchant_state.part_price);
-                    stats.add(Stat::Parts, 1);
-                    did_purchase = true;
-                    log_events.single_write(LogEvent { message: String::from("You purchase some parts."), color: COLOR_GREEN });
+                    purchase = Some((Stat::Parts, merchant_state.part_price));
+                    log_events.single_write(LogEvent { message: String::from("You purchase some parts."), color: COLOR_GREEN, reveal: false });
                 } else {
-                    log_events.single_write(LogEvent { message: String::from("You don't have enough money for that..."), color: COLOR_RED });
+                    log_events.single_write(LogEvent { message: String::from("You don't have enough money for that..."), color: COLOR_RED, reveal: false });
                 }
             }
 
-            if did_purchase {
+            if let Some((stat, price)) = purchase {
+                game_events.single_write(GameEvent::HandleStatEffects {
+                    effects: vec![
+                        StatEffect::Subtract {
+                            stat: Stat::Money,
+                            amount: price,
+                        },
+                        StatEffect::Add { stat, amount: 1 },
+                    ],
+                });
                 game_events.single_write(GameEvent::RefreshActivities);
             }
         }
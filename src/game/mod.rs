@@ -1,42 +1,61 @@
 pub mod activity;
+pub mod atlas;
 pub mod audio;
 pub mod clickable;
+pub mod coords;
+pub mod floater;
 pub mod layers;
+pub mod mail;
 pub mod merchant;
+pub mod particles;
 pub mod physics;
 pub mod render;
 pub mod resources;
+pub mod rng;
+pub mod save;
+pub mod settings;
 pub mod stats;
 pub mod time;
 pub mod transform;
+pub mod weather;
 pub mod log;
 
 use log::*;
 use activity::*;
-use audio::AudioAssetDb;
+use atlas::SpriteAtlas;
+use audio::{AudioAssetDb, AudioState, AudioSystem};
 use clickable::*;
+use coords::*;
+use floater::*;
 use gfx::{color::*, renderer::Transparency, sprite::SpriteRegion};
 use layers::*;
+use mail::*;
 use merchant::*;
+use particles::*;
 use ncollide2d::{pipeline::CollisionGroups, shape::Cuboid};
 use nphysics2d::object::BodyStatus;
 use physics::*;
-use render::{RenderState, SpriteComponent, SpriteRenderSystem};
+use render::{
+    AnimationSystem, ColliderDebugRenderSystem, RenderState, SpriteComponent, SpriteRenderSystem,
+    TextAlign,
+};
+use rng::GameRng;
+use save::SaveData;
+use settings::Settings;
+use serde::{Deserialize, Serialize};
 use shrev::EventChannel;
 use specs::prelude::*;
 use stats::*;
 use std::default::Default;
 use time::*;
 use transform::TransformComponent;
+use weather::*;
 
 pub type Vector2f = nalgebra::Vector2<f32>;
 pub type Vector2d = nalgebra::Vector2<f64>;
 pub type Point2f = nalgebra::Point2<f32>;
 pub type Point2d = nalgebra::Point2<f64>;
 
-pub const PIXELS_PER_WORLD_UNIT: u32 = 32;
-pub const PIXELS_TO_WORLD_UNITS: f64 = (1.0 / PIXELS_PER_WORLD_UNIT as f64);
-
 #[derive(Clone)]
 pub enum GameEvent {
     NewDayStarted { day: i32 },
@@ -44,7 +63,8 @@ pub enum GameEvent {
     ProgressTime { hours: i32 },
     HandleStatEffects { effects: Vec<StatEffect> },
     HandleConditionEffects { effects: Vec<ConditionEffect> },
-    GameOver,
+    Victory,
+    NewGameStarted,
     RefreshActivities,
     ActivityGoFishing,
     ActivityPerformMaintenance,
@@ -54,38 +74,125 @@ pub enum GameEvent {
     None,
 }
 
-#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum GameCondition {
     FinalDay,
     GameOver,
     GeneratorBroken,
+    GeneratorOutOfGas,
     LensBroken,
     LighthouseDamaged,
     Starving,
     Insane,
     Dread,
     Inspired,
+    Won,
+}
+
+impl std::fmt::Display for GameCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let printable = match *self {
+            GameCondition::FinalDay => "It is your final day",
+            GameCondition::GameOver => "Your watch is over",
+            GameCondition::GeneratorBroken => "The generator is broken",
+            GameCondition::GeneratorOutOfGas => "The generator is out of gas",
+            GameCondition::LensBroken => "The lens is broken",
+            GameCondition::LighthouseDamaged => "The lighthouse is damaged",
+            GameCondition::Starving => "You are starving",
+            GameCondition::Insane => "You are going insane",
+            GameCondition::Dread => "You feel a sense of dread",
+            GameCondition::Inspired => "You feel inspired",
+            GameCondition::Won => "You survived your posting",
+        };
+
+        write!(f, "{}", printable)
+    }
+}
+
+#[derive(Default)]
+pub struct GameControlState {
+    pub is_paused: bool,
+    // Set to capture the next rendered frame to a file
+    pub is_screenshot_requested: bool,
+}
+
+/// Settings for a run, which stay the same when a new game starts.
+pub struct GameConfig {
+    // The day the keeper has to survive until
+    pub final_day: i32,
+}
+
+impl GameConfig {
+    pub fn new() -> Self {
+        GameConfig { final_day: 30 }
+    }
+
+    pub fn days_remaining(&self, day: i32) -> i32 {
+        (self.final_day - day).max(0)
+    }
+}
+
+#[derive(Default)]
+pub struct DebugState {
+    pub draw_colliders: bool,
+    pub show_render_stats: bool,
+    // Print each stat effect to stdout as it's applied
+    pub print_stat_effects: bool,
+}
+
+/// Logical size of the screen, in render units. Kept in sync with the window by the main loop.
+pub struct ScreenState {
+    pub width: u32,
+    pub height: u32,
+    // Physical pixels per logical pixel, and render units per logical pixel. Used to bring the cursor into render units.
+    pub dpi_scale_factor: f32,
+    pub render_scale: f32,
 }
 
+/// Each tick runs the dispatchers in phases: `tick_dispatcher` for game logic, then `physics_dispatcher`, then
+/// the world is maintained, and `render_dispatcher` runs last. Render systems go in `render_dispatcher`, so they
+/// always draw the state that logic and physics left this tick, including entities created or deleted this tick.
 pub struct GameState<'a, 'b> {
     pub world: World,
     pub tick_dispatcher: Dispatcher<'a, 'b>,
     pub physics_dispatcher: Dispatcher<'a, 'b>,
+    pub render_dispatcher: Dispatcher<'a, 'b>,
 }
 
 impl<'a, 'b> GameState<'a, 'b> {
-    pub fn new(width: u32, height: u32) -> GameState<'a, 'b> {
+    pub fn new(width: u32, height: u32, seed: Option<u64>) -> GameState<'a, 'b> {
         let mut world = World::new();
+        let mut rng = GameRng::new(seed);
 
         // Resources
         world.insert(RenderState::new());
+        world.insert(
+            SpriteAtlas::load(resources::ATLAS_SPRITESHEET_UI)
+                .expect("Failed to load UI sprite atlas!"),
+        );
         world.insert(PhysicsState::new());
         world.insert(TimeState::new());
         world.insert(StatsState::new());
         world.insert(ActivityState::new());
-        world.insert(MerchantState::new());
+        world.insert(MerchantState::new(&mut rng));
+        world.insert(MailState::new());
+        world.insert(WeatherState::new());
         world.insert(LogState::default());
+        world.insert(FloaterState::default());
         world.insert(AudioAssetDb::new());
+        world.insert(AudioState::new());
+        world.insert(Settings::new());
+        world.insert(rng);
+        world.insert(GameControlState::default());
+        world.insert(PointerState::default());
+        world.insert(DebugState::default());
+        world.insert(GameConfig::new());
+        world.insert(ScreenState {
+            width,
+            height,
+            dpi_scale_factor: 1.0,
+            render_scale: 1.0,
+        });
         world.insert(EventChannel::<CollisionEvent>::new());
         world.insert(EventChannel::<OnClickedEvent>::new());
         world.insert(EventChannel::<LogEvent>::new());
@@ -96,12 +203,11 @@ impl<'a, 'b> GameState<'a, 'b> {
             .with(TimeSystem::default(), "time", &[])
             .with(StatsSystem::default(), "stats", &[])
             .with(MerchantSystem::default(), "merchant", &[])
-            .with(ActivitySystem::default(), "activity", &["clickable"])
-            .with_thread_local(LogSystem::default())
-            .with_thread_local(TimeInfoRenderSystem::default())
-            .with_thread_local(StatsInfoRenderSystem::default())
-            .with_thread_local(ActivityInfoRenderSystem::default())
-            .with_thread_local(SpriteRenderSystem::default())
+            .with(MailSystem::default(), "mail", &["clickable"])
+            .with(WeatherSystem::default(), "weather", &["time"])
+            .with(ActivitySystem::default(), "activity", &["clickable", "weather"])
+            .with(AudioSystem::default(), "audio", &["stats"])
+            .with(AnimationSystem::default(), "animation", &[])
             .build();
 
         tick_dispatcher.setup(&mut world);
@@ -115,6 +221,23 @@ impl<'a, 'b> GameState<'a, 'b> {
 
         physics_dispatcher.setup(&mut world);
 
+        // Thread local systems run in the order they're added, which is the order they draw in
+        let mut render_dispatcher = DispatcherBuilder::new()
+            .with_thread_local(LogSystem::default())
+            .with_thread_local(TimeInfoRenderSystem::default())
+            .with_thread_local(StatsInfoRenderSystem::default())
+            .with_thread_local(FloaterSystem::default())
+            .with_thread_local(ConditionsInfoRenderSystem::default())
+            .with_thread_local(ActivityInfoRenderSystem::default())
+            .with_thread_local(MailInfoRenderSystem::default())
+            .with_thread_local(ActivityTooltipRenderSystem::default())
+            .with_thread_local(ParticleSystem::default())
+            .with_thread_local(SpriteRenderSystem::default())
+            .with_thread_local(ColliderDebugRenderSystem::default())
+            .build();
+
+        render_dispatcher.setup(&mut world);
+
         world
             .write_resource::<EventChannel<GameEvent>>()
             .single_write(GameEvent::RefreshActivities);
@@ -123,8 +246,23 @@ impl<'a, 'b> GameState<'a, 'b> {
             world,
             tick_dispatcher,
             physics_dispatcher,
+            render_dispatcher,
         }
     }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        SaveData::from_world(&self.world).write(path)
+    }
+
+    pub fn load(path: &str, width: u32, height: u32) -> std::io::Result<GameState<'a, 'b>> {
+        let data = SaveData::read(path)?;
+
+        // The saved seed and stream position replace the new state's RNG, so the run carries on as it would have
+        let mut state = GameState::new(width, height, data.seed);
+        data.apply_to_world(&mut state.world);
+
+        Ok(state)
+    }
 }
 
 fn lerp(start: f32, end: f32, percentage: f32) -> f32 {
@@ -0,0 +1,202 @@
+use crate::game::{atlas::SpriteAtlas, *};
+use gfx::window::DeltaTime;
+use rand::Rng;
+use specs::prelude::*;
+
+struct Particle {
+    position: (f32, f32),
+    velocity: (f32, f32),
+    // Seconds since the particle was spawned
+    age: f32,
+}
+
+/// Spawns particles in a rectangle starting at the entity's position, and moves them in a straight line until they die.
+///
+/// Particles live in a pool that is allocated up front, so an emitter never allocates after it's created.
+/// Once the pool is full, no new particles spawn until old ones die.
+pub struct ParticleEmitter {
+    // Particles spawned per second, at full intensity
+    pub spawn_rate: f32,
+    // Size of the area particles spawn in, in render units
+    pub area: (f32, f32),
+    // If set, the area's width is the screen's width plus this much, so it keeps covering the screen when it resizes
+    pub screen_width_padding: Option<f32>,
+    // Each particle gets a random velocity between these, in render units per second
+    pub velocity_min: (f32, f32),
+    pub velocity_max: (f32, f32),
+    // How long each particle lives, in seconds. Particles fade out over their last quarter.
+    pub lifetime: f32,
+    pub color: Color,
+    pub region: SpriteRegion,
+    pub scale: Vector2f,
+    pub layer: Layer,
+    // If set, the emitter only spawns in this weather, and its spawn rate is scaled by the weather's intensity
+    pub weather: Option<Weather>,
+    // The most particles alive at once. The pool is allocated with room for this many.
+    max_particles: usize,
+    particles: Vec<Particle>,
+    // Fractional particles carried over between ticks, so low spawn rates still spawn
+    spawn_accumulator: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(
+        max_particles: usize,
+        spawn_rate: f32,
+        area: (f32, f32),
+        velocity_min: (f32, f32),
+        velocity_max: (f32, f32),
+        lifetime: f32,
+        color: Color,
+        region: SpriteRegion,
+        scale: Vector2f,
+        layer: Layer,
+    ) -> Self {
+        ParticleEmitter {
+            spawn_rate,
+            area,
+            screen_width_padding: None,
+            velocity_min,
+            velocity_max,
+            lifetime,
+            color,
+            region,
+            scale,
+            layer,
+            weather: None,
+            max_particles,
+            particles: Vec::with_capacity(max_particles),
+            spawn_accumulator: 0.0,
+        }
+    }
+
+    /// Only emit in the given weather, scaled by its intensity.
+    pub fn with_weather(mut self, weather: Weather) -> Self {
+        self.weather = Some(weather);
+        self
+    }
+
+    /// Make the spawn area as wide as the screen, plus `padding`, whatever size the screen is when spawning.
+    pub fn spanning_screen_width(mut self, padding: f32) -> Self {
+        self.screen_width_padding = Some(padding);
+        self
+    }
+
+    fn intensity(&self, weather: &WeatherState) -> f32 {
+        match self.weather {
+            Some(kind) if kind == weather.kind => weather.intensity,
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+}
+
+impl Component for ParticleEmitter {
+    type Storage = VecStorage<Self>;
+}
+
+#[derive(Default)]
+pub struct ParticleSystem;
+
+impl<'a> System<'a> for ParticleSystem {
+    type SystemData = (
+        Write<'a, RenderState>,
+        ReadExpect<'a, WeatherState>,
+        ReadExpect<'a, ScreenState>,
+        ReadExpect<'a, DeltaTime>,
+        ReadStorage<'a, TransformComponent>,
+        WriteStorage<'a, ParticleEmitter>,
+    );
+
+    fn run(&mut self, (mut render, weather, screen, dt, transforms, mut emitters): Self::SystemData) {
+        let mut render = render.scope();
+
+        // Particles are only for looks, so they don't take from the seeded game rng
+        let mut rng = rand::thread_rng();
+        let dt = *dt as f32;
+
+        render.bind_transparency(Transparency::Transparent);
+        render.bind_texture(resources::TEX_SPRITESHEET_UI);
+
+        for (transform, emitter) in (&transforms, &mut emitters).join() {
+            let lifetime = emitter.lifetime;
+            for particle in emitter.particles.iter_mut() {
+                particle.age += dt;
+                particle.position.0 += particle.velocity.0 * dt;
+                particle.position.1 += particle.velocity.1 * dt;
+            }
+            emitter.particles.retain(|particle| particle.age < lifetime);
+
+            let area_width = match emitter.screen_width_padding {
+                Some(padding) => screen.width as f32 + padding,
+                None => emitter.area.0,
+            };
+
+            emitter.spawn_accumulator += emitter.spawn_rate * emitter.intensity(&weather) * dt;
+            while emitter.spawn_accumulator >= 1.0 {
+                emitter.spawn_accumulator -= 1.0;
+
+                // Don't grow the pool past its size
+                if emitter.particles.len() >= emitter.max_particles {
+                    continue;
+                }
+
+                let x = transform.position.x as f32 + (area_width * rng.gen::<f32>());
+                let y = transform.position.y as f32 + (emitter.area.1 * rng.gen::<f32>());
+                let velocity = (
+                    lerp(emitter.velocity_min.0, emitter.velocity_max.0, rng.gen()),
+                    lerp(emitter.velocity_min.1, emitter.velocity_max.1, rng.gen()),
+                );
+
+                emitter.particles.push(Particle { position: (x, y), velocity, age: 0.0 });
+            }
+
+            // Every particle shares a texture and layer, so an emitter's particles all end up in one batch
+            render.bind_layer(emitter.layer);
+            let pivot = Point2f::new(emitter.region.w as f32 / 2.0, emitter.region.h as f32 / 2.0);
+            for particle in emitter.particles.iter() {
+                let remaining = (lifetime - particle.age) / lifetime;
+                let alpha = (remaining * 4.0).min(1.0);
+
+                render.bind_color(emitter.color.with_alpha(emitter.color.a * alpha));
+                render.sprite(particle.position.0, particle.position.1, pivot, emitter.scale, emitter.region);
+            }
+        }
+    }
+}
+
+/// Remove every emitter from the world, particles and all, so they can be put back with `create_weather_ents`.
+pub fn take_particle_emitters(world: &mut World) -> Vec<ParticleEmitter> {
+    world.write_storage::<ParticleEmitter>().drain().join().collect()
+}
+
+/// Create the rain that falls during storms. Like the rest of the scene, this is rebuilt along with the activities,
+/// and reuses the emitter taken from the old scene if there is one.
+pub fn create_weather_ents(world: &mut World, mut previous: Vec<ParticleEmitter>) {
+    let droplet_region = world.read_resource::<SpriteAtlas>().region("particle");
+
+    let rain = previous.pop().unwrap_or_else(|| {
+        ParticleEmitter::new(
+            512,
+            300.0,
+            // Only the height is used, since the width follows the screen
+            (0.0, 0.0),
+            (-70.0, 500.0),
+            (-40.0, 650.0),
+            1.6,
+            Color::rgba_f32(0.75, 0.8, 0.9, 0.6),
+            droplet_region,
+            Vector2f::new(0.5, 3.0),
+            Layer::Glass,
+        )
+        .with_weather(Weather::Storm)
+        // Start past the right edge, since the rain blows to the left
+        .spanning_screen_width(64.0)
+    });
+
+    world
+        .create_entity()
+        .with(TransformComponent::new(Vector2d::new(0.0, -24.0), Vector2f::new(1.0, 1.0)))
+        .with(rain)
+        .build();
+}
@@ -1,6 +1,8 @@
 use crate::game::{
-    render::SpriteComponent, transform::TransformComponent, Point2d, Vector2d,
-    PIXELS_PER_WORLD_UNIT, PIXELS_TO_WORLD_UNITS,
+    coords::{screen_to_world, world_to_screen},
+    render::SpriteComponent,
+    transform::TransformComponent,
+    Point2d, Vector2d,
 };
 use nalgebra::{Isometry2, Vector2};
 use ncollide2d::pipeline::InterferencesWithPoint;
@@ -13,7 +15,7 @@ use nphysics2d::{
     joint::DefaultJointConstraintSet,
     math::Velocity,
     object::{
-        Body, BodyPartHandle, BodyStatus, ColliderDesc, DefaultBodyHandle, DefaultBodySet,
+        Body, BodyPartHandle, BodyStatus, Collider, ColliderDesc, DefaultBodyHandle, DefaultBodySet,
         DefaultColliderHandle, DefaultColliderSet, Ground, RigidBodyDesc,
     },
     world::{DefaultGeometricalWorld, DefaultMechanicalWorld},
@@ -96,6 +98,8 @@ impl PhysicsState {
         );
     }
 
+    /// All colliders containing the point, in world space.
+    /// Not every collider has to belong to an entity, so use `collider_entity` to get the entity of each one.
     pub fn interferences_with_point<'a, 'b>(
         &'a self,
         point: &'b Point2d,
@@ -106,6 +110,11 @@ impl PhysicsState {
     }
 }
 
+/// The entity a collider belongs to, if it was created for one.
+pub fn collider_entity(collider: &Collider<f64, DefaultBodyHandle>) -> Option<Entity> {
+    collider.user_data().and_then(|d| d.downcast_ref::<Entity>()).cloned()
+}
+
 #[derive(Debug)]
 pub struct RigidbodyComponent {
     pub handle: Option<DefaultBodyHandle>,
@@ -139,6 +148,17 @@ impl Component for RigidbodyComponent {
     type Storage = FlaggedStorage<Self, VecStorage<Self>>;
 }
 
+/// Scale the linear part of a velocity down so its speed is at most `max_linear_velocity`, keeping its direction.
+/// nphysics' own limit is broken (https://github.com/rustsim/nphysics/pull/254), so bodies are clamped with this instead.
+pub fn clamp_linear_velocity(velocity: Velocity<f64>, max_linear_velocity: f64) -> Velocity<f64> {
+    let speed = velocity.linear.norm();
+    if speed <= max_linear_velocity {
+        return velocity;
+    }
+
+    Velocity::new(velocity.linear * (max_linear_velocity / speed), velocity.angular)
+}
+
 pub struct ColliderComponent {
     pub shape: ShapeHandle<f64>,
     pub center: Vector2<f64>,
@@ -256,16 +276,13 @@ impl<'a> System<'a> for RigidbodySendPhysicsSystem {
             }
 
             let rigid_body = RigidBodyDesc::new()
-                .translation(transform.position * PIXELS_TO_WORLD_UNITS)
+                .translation(screen_to_world(transform.position))
                 .rotation(0.0)
                 .gravity_enabled(false)
                 .status(rigidbody.status)
-                .velocity(rigidbody.velocity)
+                .velocity(clamp_linear_velocity(rigidbody.velocity, rigidbody.max_linear_velocity))
                 .mass(rigidbody.mass)
                 .linear_motion_interpolation_enabled(true)
-                // TODO uncomment once bugfix is released:
-                // https://github.com/rustsim/nphysics/pull/254
-                //.max_linear_velocity(rigidbody.max_linear_velocity)
                 .user_data(ent)
                 .build();
 
@@ -284,7 +301,7 @@ impl<'a> System<'a> for RigidbodySendPhysicsSystem {
         for (ent, rigidbody, ent_id) in (&entities, &rigidbodies, &self.modified_bodies).join() {
             if let Some(rb_handle) = physics.ent_body_handles.get(&ent.id()).cloned() {
                 let rb = physics.bodies.rigid_body_mut(rb_handle).unwrap();
-                rb.set_velocity(rigidbody.velocity);
+                rb.set_velocity(clamp_linear_velocity(rigidbody.velocity, rigidbody.max_linear_velocity));
                 rb.set_status(rigidbody.status);
             } else {
                 eprintln!("[RigidbodySendPhysicsSystem] Failed to update rigidbody because it didn't exist! Entity Id = {}", ent_id);
@@ -303,7 +320,7 @@ impl<'a> System<'a> for RigidbodySendPhysicsSystem {
             if let Some(rb_handle) = physics.ent_body_handles.get(&ent.id()).cloned() {
                 let rb = physics.bodies.rigid_body_mut(rb_handle).unwrap();
                 rb.set_position(Isometry2::new(
-                    transform.position * PIXELS_TO_WORLD_UNITS,
+                    screen_to_world(transform.position),
                     0.0,
                 ));
             } else {
@@ -426,13 +443,12 @@ impl<'a> System<'a> for ColliderSendPhysicsSystem {
                 if let Some(rb_handle) = physics.ent_body_handles.get(&ent.id()) {
                     (
                         rb_handle.clone(),
-                        (collider.center + collider.offset) * PIXELS_TO_WORLD_UNITS,
+                        screen_to_world(collider.center + collider.offset),
                     )
                 } else {
                     (
                         physics.ground_body_handle.clone(),
-                        (transform.position + collider.center + collider.offset)
-                            * PIXELS_TO_WORLD_UNITS,
+                        screen_to_world(transform.position + collider.center + collider.offset),
                     )
                 };
 
@@ -484,8 +500,7 @@ impl<'a> System<'a> for ColliderSendPhysicsSystem {
             if let Some(collider_handle) = physics.ent_collider_handles.get(&ent.id()).cloned() {
                 let phys_collider = physics.colliders.get_mut(collider_handle).unwrap();
                 phys_collider.set_position(Isometry2::new(
-                    (transform.position + collider.center + collider.offset)
-                        * PIXELS_TO_WORLD_UNITS,
+                    screen_to_world(transform.position + collider.center + collider.offset),
                     0.0,
                 ));
             } else {
@@ -527,23 +542,13 @@ impl<'a> System<'a> for WorldStepPhysicsSystem {
                         .geometrical_world
                         .contact_pair(&physics.colliders, *handle1, *handle2, false)
                     {
-                        let entity_a = collider_a
-                            .user_data()
-                            .unwrap()
-                            .downcast_ref::<Entity>()
-                            .cloned();
-                        let entity_b = collider_b
-                            .user_data()
-                            .unwrap()
-                            .downcast_ref::<Entity>()
-                            .cloned();
+                        let entity_a = collider_entity(collider_a);
+                        let entity_b = collider_entity(collider_b);
 
                         let (normal, collision_a_point, collision_b_point) =
                             if let Some(c) = manifold.deepest_contact().cloned() {
-                                let collision_a_point =
-                                    c.contact.world1 * (PIXELS_PER_WORLD_UNIT as f64);
-                                let collision_b_point =
-                                    c.contact.world2 * (PIXELS_PER_WORLD_UNIT as f64);
+                                let collision_a_point = world_to_screen(c.contact.world1);
+                                let collision_b_point = world_to_screen(c.contact.world2);
                                 (
                                     Some(c.contact.normal.into_inner()),
                                     Some(collision_a_point),
@@ -580,10 +585,40 @@ impl<'a> System<'a> for WorldStepPhysicsSystem {
                         None
                     }
                 }
-                ContactEvent::Stopped(_handle1, _handle2) => {
+                ContactEvent::Stopped(handle1, handle2) => {
                     //println!("contact stopped: handle1: {:?}, handle2: {:?}", handle1, handle2);
-                    // TODO
-                    None
+                    // The contact pair is gone by now, so look up the colliders directly.
+                    // Either collider may have been removed already, in which case it has no entity.
+                    let entity_a = physics
+                        .colliders
+                        .get(*handle1)
+                        .and_then(collider_entity);
+                    let entity_b = physics
+                        .colliders
+                        .get(*handle2)
+                        .and_then(collider_entity);
+
+                    let event_a = CollisionEvent {
+                        entity_a,
+                        collider_handle_a: *handle1,
+                        entity_b,
+                        collider_handle_b: *handle2,
+                        normal: None,
+                        collision_point: None,
+                        ty: CollisionType::Stopped,
+                    };
+
+                    let event_b = CollisionEvent {
+                        entity_a: entity_b,
+                        collider_handle_a: *handle2,
+                        entity_b: entity_a,
+                        collider_handle_b: *handle1,
+                        normal: None,
+                        collision_point: None,
+                        ty: CollisionType::Stopped,
+                    };
+
+                    Some(vec![event_a, event_b])
                 }
             };
 
@@ -609,10 +644,144 @@ impl<'a> System<'a> for RigidbodyReceivePhysicsSystem {
                 transform.last_position = transform.position;
                 rigidbody.last_velocity = rigidbody.velocity.clone();
 
-                transform.position =
-                    body.position().translation.vector * PIXELS_PER_WORLD_UNIT as f64;
-                rigidbody.velocity = body.velocity().clone();
+                transform.position = world_to_screen(body.position().translation.vector);
+
+                // Writing the clamped velocity marks the rigidbody as modified,
+                // so it's sent back to the body before the next step
+                rigidbody.velocity = clamp_linear_velocity(*body.velocity(), rigidbody.max_linear_velocity);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Vector2f;
+    use ncollide2d::shape::Ball;
+
+    fn insert_ball(physics: &mut PhysicsState, ent: Entity, x: f64) -> DefaultBodyHandle {
+        let body = RigidBodyDesc::new()
+            .translation(Vector2::new(x, 0.0))
+            .gravity_enabled(false)
+            .build();
+        let handle = physics.bodies.insert(body);
+
+        let collider = ColliderDesc::new(ShapeHandle::new(Ball::new(1.0)))
+            .user_data(ent)
+            .build(BodyPartHandle(handle, 0));
+        physics.colliders.insert(collider);
+
+        handle
+    }
+
+    fn read_collisions(world: &World, reader: &mut ReaderId<CollisionEvent>, stopped: bool) -> Vec<(Option<Entity>, Option<Entity>)> {
+        world
+            .fetch::<EventChannel<CollisionEvent>>()
+            .read(reader)
+            .filter(|event| match event.ty {
+                CollisionType::Started => !stopped,
+                CollisionType::Stopped => stopped,
+            })
+            .map(|event| (event.entity_a, event.entity_b))
+            .collect()
+    }
+
+    #[test]
+    fn separating_bodies_emit_stopped_pair() {
+        let mut world = World::new();
+        world.insert(PhysicsState::new());
+        world.insert(EventChannel::<CollisionEvent>::new());
+        let mut reader = world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader();
+
+        let ent_a = world.create_entity().build();
+        let ent_b = world.create_entity().build();
+        let body_b = {
+            let mut physics = world.write_resource::<PhysicsState>();
+            insert_ball(&mut physics, ent_a, 0.0);
+            insert_ball(&mut physics, ent_b, 1.0)
+        };
+
+        WorldStepPhysicsSystem.run_now(&world);
+        let started = read_collisions(&world, &mut reader, false);
+        assert_eq!(started.len(), 2);
+
+        // Pull the bodies apart
+        world
+            .write_resource::<PhysicsState>()
+            .bodies
+            .rigid_body_mut(body_b)
+            .unwrap()
+            .set_position(Isometry2::new(Vector2::new(100.0, 0.0), 0.0));
+
+        WorldStepPhysicsSystem.run_now(&world);
+        let stopped = read_collisions(&world, &mut reader, true);
+        assert_eq!(stopped.len(), 2);
+        assert!(stopped.contains(&(Some(ent_a), Some(ent_b))));
+        assert!(stopped.contains(&(Some(ent_b), Some(ent_a))));
+    }
+
+    #[test]
+    fn clamp_linear_velocity_caps_speed() {
+        let max_linear_velocity = 10.0;
+        let velocity = Velocity::new(Vector2::new(3000.0, -4000.0), 1.5);
+        let clamped = clamp_linear_velocity(velocity, max_linear_velocity);
+
+        assert!(clamped.linear.norm() <= max_linear_velocity + 1e-9);
+        // Direction and spin are kept
+        assert!((clamped.linear.x - 6.0).abs() < 1e-9);
+        assert!((clamped.linear.y + 8.0).abs() < 1e-9);
+        assert_eq!(clamped.angular, 1.5);
+    }
+
+    #[test]
+    fn fast_rigidbody_is_clamped_through_the_systems() {
+        let mut world = World::new();
+        world.insert(PhysicsState::new());
+        world.insert(EventChannel::<CollisionEvent>::new());
+        world.register::<TransformComponent>();
+        world.register::<RigidbodyComponent>();
+
+        let mut send_system = RigidbodySendPhysicsSystem::default();
+        System::setup(&mut send_system, &mut world);
+
+        let max_linear_velocity = 10.0;
+        let ent = world
+            .create_entity()
+            .with(TransformComponent::new(Vector2d::new(0.0, 0.0), Vector2f::new(1.0, 1.0)))
+            .with(RigidbodyComponent::new(1.0, Vector2::new(5000.0, 3000.0), max_linear_velocity, BodyStatus::Dynamic))
+            .build();
+
+        // Step once, and return the speed of the component and of its body in the physics world
+        let run_step = |world: &World, send_system: &mut RigidbodySendPhysicsSystem| {
+            send_system.run_now(world);
+            WorldStepPhysicsSystem.run_now(world);
+            RigidbodyReceivePhysicsSystem.run_now(world);
+
+            let rigidbodies = world.read_storage::<RigidbodyComponent>();
+            let rigidbody = rigidbodies.get(ent).unwrap();
+            let physics = world.read_resource::<PhysicsState>();
+            let body = physics.bodies.rigid_body(rigidbody.handle.unwrap()).unwrap();
+
+            (rigidbody.velocity.linear.norm(), body.velocity().linear.norm())
+        };
+
+        let (speed, body_speed) = run_step(&world, &mut send_system);
+        assert!(speed <= max_linear_velocity + 1e-9, "speed {} is over the max", speed);
+        assert!(body_speed <= max_linear_velocity + 1e-9, "body speed {} is over the max", body_speed);
+
+        // Speeding up a body that's already in the physics world is clamped too
+        world.write_storage::<RigidbodyComponent>().get_mut(ent).unwrap().velocity = Velocity::new(Vector2::new(-8000.0, 0.0), 0.0);
+        let (speed, body_speed) = run_step(&world, &mut send_system);
+        assert!(speed <= max_linear_velocity + 1e-9, "speed {} is over the max", speed);
+        assert!(body_speed <= max_linear_velocity + 1e-9, "body speed {} is over the max", body_speed);
+    }
+
+    #[test]
+    fn clamp_linear_velocity_leaves_slow_bodies_alone() {
+        let velocity = Velocity::new(Vector2::new(1.0, 2.0), 0.0);
+        let clamped = clamp_linear_velocity(velocity, 10.0);
+
+        assert_eq!(clamped.linear, velocity.linear);
+    }
+}
@@ -1,25 +1,55 @@
 use crate::game::{
+    coords::world_to_screen,
+    layers::{self, Layer},
     physics::{ColliderComponent, PhysicsState, RigidbodyComponent},
     transform::TransformComponent,
-    Point2d, Vector2d,
+    DebugState, Point2d, Vector2d,
 };
 use gfx::{
     color::*,
     renderer::{Renderable, TextureId, Transparency},
     sprite::*,
+    window::DeltaTime,
     Point2f, Vector2f,
 };
-use ncollide2d::{procedural::Polyline, shape::Shape, transformation::ToPolyline};
+use ncollide2d::{
+    procedural::Polyline,
+    shape::{Ball, Cuboid, Shape},
+    transformation::ToPolyline,
+};
 use specs::prelude::*;
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+// Everything set by the bind functions
+#[derive(Clone, Copy)]
+struct BoundState {
+    transparency: Transparency,
+    texture_id: TextureId,
+    layer: u8,
+    z_order: u16,
+    color: Color,
+}
 
 #[derive(Default)]
 pub struct RenderState {
     commands: Vec<gfx::renderer::RenderCommand>,
+    // The commands from the last finished tick, kept so they can be drawn by every render until the next one finishes
+    tick_commands: Vec<gfx::renderer::RenderCommand>,
     bound_transparency: Transparency,
     bound_texture_id: TextureId,
     bound_layer: u8,
+    bound_z_order: u16,
     bound_color: Color,
+
+    // Per-glyph advance widths (in unscaled pixels) for font textures that have them
+    glyph_widths: HashMap<TextureId, Vec<u32>>,
 }
 
 impl RenderState {
@@ -37,8 +67,16 @@ impl RenderState {
         self.bound_texture_id = val;
     }
 
-    pub fn bind_layer(&mut self, val: u8) {
-        self.bound_layer = val;
+    /// Binding a layer also resets the z order, so each system starts at the bottom of its layer.
+    pub fn bind_layer(&mut self, val: Layer) {
+        self.bound_layer = val.value();
+        self.bound_z_order = 0;
+    }
+
+    /// Order within the bound layer. Higher z orders are always drawn on top of lower ones in the same layer,
+    /// whatever their texture or shader, so e.g. text can be kept above the panel behind it.
+    pub fn bind_z_order(&mut self, val: u16) {
+        self.bound_z_order = val;
     }
 
     pub fn bind_color(&mut self, val: Color) {
@@ -52,51 +90,122 @@ impl RenderState {
         pivot: Point2f,
         scale: Vector2f,
         region: SpriteRegion,
+    ) {
+        self.sprite_rotated(x, y, pivot, scale, 0.0, region);
+    }
+
+    /// Draw a sprite rotated around its pivot. `rotation` is in radians.
+    pub fn sprite_rotated(
+        &mut self,
+        x: f32,
+        y: f32,
+        pivot: Point2f,
+        scale: Vector2f,
+        rotation: f32,
+        region: SpriteRegion,
     ) {
         self.commands.push(gfx::renderer::RenderCommand {
             transparency: self.bound_transparency,
             shader_program_id: 1,
             tex_id: self.bound_texture_id,
             layer: self.bound_layer,
+            z_order: self.bound_z_order,
             data: Renderable::Sprite {
                 x,
                 y,
                 pivot,
                 scale,
+                rotation,
                 color: self.bound_color,
                 region,
             },
         });
     }
 
+    /// Draw `region` as a 9-patch filling the rect at (x, y) with size (w, h).
+    /// The corners keep their size, the edges stretch along one axis, and the center stretches along both.
+    pub fn nine_slice(&mut self, x: f32, y: f32, w: f32, h: f32, region: SpriteRegion, border: u32) {
+        for (dest, src) in nine_slice_rects(x, y, w, h, region, border).iter() {
+            // Slices can be empty when the region or rect is too small for the border
+            if (src.w == 0) || (src.h == 0) || (dest.2 <= 0.0) || (dest.3 <= 0.0) {
+                continue;
+            }
+
+            let scale = Vector2f::new(dest.2 / src.w as f32, dest.3 / src.h as f32);
+            self.sprite(dest.0, dest.1, Point2f::origin(), scale, *src);
+        }
+    }
+
+    /// Use variable width glyphs when drawing text with the given font texture.
+    /// `widths` holds the advance of each ascii glyph, in pixels.
+    pub fn set_glyph_widths(&mut self, font_tex_id: TextureId, widths: Vec<u32>) {
+        self.glyph_widths.insert(font_tex_id, widths);
+    }
+
     pub fn text(&mut self, x: f32, y: f32, w: u32, h: u32, scale: f32, text: &str) {
-        let cols: u32 = 16;
-        for (i, c) in text.chars().enumerate() {
+        let mut advance_x: f32 = 0.0;
+        for c in text.chars() {
             let ascii: u8 = c as u8;
-            let sprite_col: u32 = ascii as u32 % cols;
-            let sprite_row: u32 = ascii as u32 / cols;
+            let glyph_x = x + advance_x;
+            advance_x += self.glyph_advance(ascii, w, scale);
+
             self.commands.push(gfx::renderer::RenderCommand {
                 transparency: self.bound_transparency,
                 shader_program_id: 1,
                 tex_id: self.bound_texture_id,
                 layer: self.bound_layer,
-                data: Renderable::Sprite {
-                    x: x + (i as f32 * (w as f32 * scale)),
-                    y: y,
-                    pivot: Point2f::origin(),
-                    scale: Vector2f::new(scale, scale),
+                z_order: self.bound_z_order,
+                data: Renderable::Glyph {
+                    x: glyph_x,
+                    y,
+                    scale,
                     color: self.bound_color,
-                    region: SpriteRegion {
-                        x: sprite_col * w,
-                        y: sprite_row * h,
-                        w,
-                        h,
-                    },
+                    ascii,
+                    cell_w: w,
+                    cell_h: h,
                 },
             });
         }
     }
 
+    /// Draw text with `x` at its left edge, center, or right edge.
+    pub fn text_aligned(
+        &mut self,
+        x: f32,
+        y: f32,
+        w: u32,
+        h: u32,
+        scale: f32,
+        align: TextAlign,
+        text: &str,
+    ) {
+        let start_x = match align {
+            TextAlign::Left => x,
+            TextAlign::Center => x - (self.measure_text(text, w, h, scale).0 / 2.0),
+            TextAlign::Right => x - self.measure_text(text, w, h, scale).0,
+        };
+
+        self.text(start_x, y, w, h, scale, text);
+    }
+
+    /// Measure the size in pixels that `text` would take up if drawn with the bound font texture.
+    pub fn measure_text(&self, text: &str, w: u32, h: u32, scale: f32) -> (f32, f32) {
+        let width = text
+            .chars()
+            .map(|c| self.glyph_advance(c as u8, w, scale))
+            .sum();
+
+        (width, h as f32 * scale)
+    }
+
+    fn glyph_advance(&self, ascii: u8, w: u32, scale: f32) -> f32 {
+        // Fall back to a fixed grid if this font has no glyph widths
+        match self.glyph_widths.get(&self.bound_texture_id) {
+            Some(widths) => *widths.get(ascii as usize).unwrap_or(&w) as f32 * scale,
+            None => w as f32 * scale,
+        }
+    }
+
     pub fn textured_quad(
         &mut self,
         bl: (f32, f32),
@@ -109,6 +218,7 @@ impl RenderState {
             shader_program_id: 1,
             tex_id: self.bound_texture_id,
             layer: self.bound_layer,
+            z_order: self.bound_z_order,
             data: Renderable::Quad {
                 bl,
                 br,
@@ -119,17 +229,277 @@ impl RenderState {
         });
     }
 
+    /// Draw a filled circle. Untextured, so it only uses the bound layer and transparency.
+    pub fn circle(&mut self, cx: f32, cy: f32, radius: f32, segments: u32, color: Color) {
+        let segments = segments.max(3);
+        let verts = (0..segments)
+            .map(|i| {
+                let angle = (i as f32 / segments as f32) * std::f32::consts::PI * 2.0;
+                (cx + (angle.cos() * radius), cy + (angle.sin() * radius))
+            })
+            .collect();
+
+        self.commands.push(gfx::renderer::RenderCommand {
+            transparency: self.bound_transparency,
+            shader_program_id: 0,
+            tex_id: 0,
+            layer: self.bound_layer,
+            z_order: self.bound_z_order,
+            data: Renderable::Polygon { verts, color },
+        });
+    }
+
+    /// Draw a solid rectangle. Untextured, so it only uses the bound layer and transparency.
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: Color) {
+        self.commands.push(gfx::renderer::RenderCommand {
+            transparency: self.bound_transparency,
+            shader_program_id: 0,
+            tex_id: 0,
+            layer: self.bound_layer,
+            z_order: self.bound_z_order,
+            data: Renderable::Polygon {
+                verts: vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)],
+                color,
+            },
+        });
+    }
+
+    /// Draw connected line segments through `points`. Untextured, so it only uses the bound layer and transparency.
+    pub fn line(&mut self, points: &[(f32, f32)], color: Color) {
+        self.commands.push(gfx::renderer::RenderCommand {
+            transparency: self.bound_transparency,
+            shader_program_id: 2,
+            tex_id: 0,
+            layer: self.bound_layer,
+            z_order: self.bound_z_order,
+            data: Renderable::LineStrip {
+                points: points.to_vec(),
+                color,
+            },
+        });
+    }
+
+    /// Start a scope that puts the bound state back the way it was when the scope ends.
+    /// Render systems draw through one, so whatever they bind doesn't leak into the next system.
+    pub fn scope(&mut self) -> RenderScope {
+        let saved = self.bound_state();
+        RenderScope { render: self, saved }
+    }
+
+    fn bound_state(&self) -> BoundState {
+        BoundState {
+            transparency: self.bound_transparency,
+            texture_id: self.bound_texture_id,
+            layer: self.bound_layer,
+            z_order: self.bound_z_order,
+            color: self.bound_color,
+        }
+    }
+
+    fn restore_bound_state(&mut self, state: BoundState) {
+        self.bound_transparency = state.transparency;
+        self.bound_texture_id = state.texture_id;
+        self.bound_layer = state.layer;
+        self.bound_z_order = state.z_order;
+        self.bound_color = state.color;
+    }
+
+    /// Reset the bound state. The commands themselves are handed off with `take_commands` or `finish_tick`.
     pub fn clear_commands(&mut self) {
         self.bound_transparency = Transparency::default();
         self.bound_texture_id = 0;
         self.bound_layer = 0;
+        self.bound_z_order = 0;
         self.bound_color = Color::default();
-        self.commands.clear();
     }
 
-    pub fn commands(&mut self) -> Vec<gfx::renderer::RenderCommand> {
-        self.commands.clone()
+    /// Take ownership of the commands pushed so far, leaving an empty list.
+    pub fn take_commands(&mut self) -> Vec<gfx::renderer::RenderCommand> {
+        std::mem::take(&mut self.commands)
+    }
+
+    /// Swap the commands pushed this tick in as the ones to draw, replacing the last tick's.
+    pub fn finish_tick(&mut self) {
+        self.tick_commands = self.take_commands();
     }
+
+    pub fn tick_commands(&self) -> &[gfx::renderer::RenderCommand] {
+        &self.tick_commands
+    }
+}
+
+/// Draws to a `RenderState`, and restores its bound state when dropped. See `RenderState::scope`.
+pub struct RenderScope<'a> {
+    render: &'a mut RenderState,
+    saved: BoundState,
+}
+
+impl<'a> std::ops::Deref for RenderScope<'a> {
+    type Target = RenderState;
+
+    fn deref(&self) -> &RenderState {
+        self.render
+    }
+}
+
+impl<'a> std::ops::DerefMut for RenderScope<'a> {
+    fn deref_mut(&mut self) -> &mut RenderState {
+        self.render
+    }
+}
+
+impl<'a> Drop for RenderScope<'a> {
+    fn drop(&mut self) {
+        self.render.restore_bound_state(self.saved);
+    }
+}
+
+/// The nine (destination rect, source region) pairs of a 9-patch, in rows from the top left.
+/// Destination rects are (x, y, w, h). The border is shrunk if the region or rect is too small to fit it on both sides.
+pub fn nine_slice_rects(
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    region: SpriteRegion,
+    border: u32,
+) -> [((f32, f32, f32, f32), SpriteRegion); 9] {
+    let src_border_x = border.min(region.w / 2);
+    let src_border_y = border.min(region.h / 2);
+    let dest_border_x = (src_border_x as f32).min(w / 2.0);
+    let dest_border_y = (src_border_y as f32).min(h / 2.0);
+
+    // Start and size of each column and row, in source pixels and destination units
+    let src_cols = [
+        (region.x, src_border_x),
+        (region.x + src_border_x, region.w - (src_border_x * 2)),
+        (region.x + region.w - src_border_x, src_border_x),
+    ];
+    let src_rows = [
+        (region.y, src_border_y),
+        (region.y + src_border_y, region.h - (src_border_y * 2)),
+        (region.y + region.h - src_border_y, src_border_y),
+    ];
+    let dest_cols = [
+        (x, dest_border_x),
+        (x + dest_border_x, w - (dest_border_x * 2.0)),
+        (x + w - dest_border_x, dest_border_x),
+    ];
+    let dest_rows = [
+        (y, dest_border_y),
+        (y + dest_border_y, h - (dest_border_y * 2.0)),
+        (y + h - dest_border_y, dest_border_y),
+    ];
+
+    let slice = |i: usize| {
+        let (row, col) = (i / 3, i % 3);
+        (
+            (dest_cols[col].0, dest_rows[row].0, dest_cols[col].1, dest_rows[row].1),
+            SpriteRegion {
+                x: src_cols[col].0,
+                y: src_rows[row].0,
+                w: src_cols[col].1,
+                h: src_rows[row].1,
+            },
+        )
+    };
+
+    [
+        slice(0),
+        slice(1),
+        slice(2),
+        slice(3),
+        slice(4),
+        slice(5),
+        slice(6),
+        slice(7),
+        slice(8),
+    ]
+}
+
+/// Split text into lines that fit within `max_width` pixels, breaking on whitespace.
+/// `measure` gives the width of a string in pixels, usually with `RenderState::measure_text` so that variable
+/// width glyphs are accounted for. Words that are too long to fit on a line by themselves are hard broken.
+pub fn wrap_text(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word = word;
+        while measure(word) > max_width {
+            if !line.is_empty() {
+                lines.push(std::mem::replace(&mut line, String::new()));
+            }
+
+            // Break after the longest prefix that fits, but always take at least one character
+            let first_split = word.char_indices().nth(1).map_or(word.len(), |(i, _)| i);
+            let split = word
+                .char_indices()
+                .map(|(i, _)| i)
+                .skip(1)
+                .take_while(|&i| measure(&word[..i]) <= max_width)
+                .last()
+                .unwrap_or(first_split);
+
+            lines.push(word[..split].to_owned());
+            word = &word[split..];
+        }
+
+        if word.is_empty() {
+            continue;
+        }
+
+        if line.is_empty() {
+            line.push_str(word);
+            continue;
+        }
+
+        let candidate = format!("{} {}", line, word);
+        if measure(&candidate) > max_width {
+            lines.push(std::mem::replace(&mut line, word.to_owned()));
+        } else {
+            line = candidate;
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Measure the advance width of each glyph in a 16x16 grid font sheet, based on its rightmost opaque column.
+/// Empty glyphs (like space) get half of a cell.
+pub fn compute_glyph_widths(pixels: &[u8], sheet_w: u32, glyph_w: u32, glyph_h: u32) -> Vec<u32> {
+    let cols: u32 = 16;
+    let mut widths = Vec::with_capacity(256);
+
+    for ascii in 0..256 {
+        let cell_x = (ascii % cols) * glyph_w;
+        let cell_y = (ascii / cols) * glyph_h;
+
+        let mut rightmost: Option<u32> = None;
+        for py in cell_y..(cell_y + glyph_h) {
+            for px in cell_x..(cell_x + glyph_w) {
+                let alpha_idx = (((py * sheet_w) + px) * 4 + 3) as usize;
+                if pixels.get(alpha_idx).cloned().unwrap_or(0) > 0 {
+                    let col = px - cell_x;
+                    rightmost = Some(rightmost.map_or(col, |r| r.max(col)));
+                }
+            }
+        }
+
+        // Leave a pixel of spacing after each glyph
+        let width = match rightmost {
+            Some(col) => (col + 2).min(glyph_w),
+            None => glyph_w / 2,
+        };
+
+        widths.push(width);
+    }
+
+    widths
 }
 
 #[derive(Debug)]
@@ -139,7 +509,11 @@ pub struct SpriteComponent {
     pub pivot: Point2f,
     pub pivot_pixels: Point2f,
     pub color: Color,
-    pub layer: u8,
+    // Multiplied into the color channels, without touching alpha. White leaves the sprite as is.
+    pub tint: Color,
+    // Multiplied into the color's alpha. Only has an effect on transparent sprites.
+    pub alpha: f32,
+    pub layer: Layer,
     pub transparency: Transparency,
 }
 
@@ -149,7 +523,7 @@ impl SpriteComponent {
         spritesheet: TextureId,
         pivot: Point2f,
         color: Color,
-        layer: u8,
+        layer: Layer,
         transparency: Transparency,
     ) -> Self {
         let pivot_pixels = Point2f::new(pivot.x * region.w as f32, pivot.y * region.h as f32);
@@ -160,6 +534,8 @@ impl SpriteComponent {
             pivot,
             pivot_pixels,
             color,
+            tint: COLOR_WHITE,
+            alpha: 1.0,
             layer,
             transparency,
         }
@@ -167,11 +543,108 @@ impl SpriteComponent {
 }
 
 impl Component for SpriteComponent {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+pub struct AnimatedSpriteComponent {
+    pub frames: Vec<SpriteRegion>,
+    pub frames_per_second: f32,
+    pub is_looping: bool,
+    current_frame: usize,
+    frame_time: f64,
+}
+
+impl AnimatedSpriteComponent {
+    pub fn new(frames: Vec<SpriteRegion>, frames_per_second: f32, is_looping: bool) -> Self {
+        AnimatedSpriteComponent {
+            frames,
+            frames_per_second,
+            is_looping,
+            current_frame: 0,
+            frame_time: 0.0,
+        }
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    /// Whether a non-looping animation has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        !self.is_looping && (self.current_frame + 1 >= self.frames.len())
+    }
+
+    /// Advance the animation by `dt` seconds.
+    pub fn advance(&mut self, dt: f64) {
+        if self.frames.is_empty() || self.frames_per_second <= 0.0 {
+            return;
+        }
+
+        let seconds_per_frame = 1.0 / self.frames_per_second as f64;
+        self.frame_time += dt;
+        while self.frame_time >= seconds_per_frame {
+            self.frame_time -= seconds_per_frame;
+
+            if self.current_frame + 1 < self.frames.len() {
+                self.current_frame += 1;
+            } else if self.is_looping {
+                self.current_frame = 0;
+            } else {
+                self.frame_time = 0.0;
+                break;
+            }
+        }
+    }
+}
+
+impl Component for AnimatedSpriteComponent {
     type Storage = VecStorage<Self>;
 }
 
+/// Advances animations, and writes the current frame into the entity's `SpriteComponent`.
+/// Must run before `SpriteRenderSystem`.
+#[derive(Default)]
+pub struct AnimationSystem;
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DeltaTime>,
+        WriteStorage<'a, AnimatedSpriteComponent>,
+        WriteStorage<'a, SpriteComponent>,
+    );
+
+    fn run(&mut self, (ents, dt, mut animations, mut sprites): Self::SystemData) {
+        // Only write sprites whose frame changed, so they aren't flagged as modified every tick
+        let mut frame_changes: Vec<(Entity, SpriteRegion)> = Vec::new();
+        for (ent, animation, sprite) in (&ents, &mut animations, &sprites).join() {
+            animation.advance(*dt);
+
+            if let Some(region) = animation.frames.get(animation.current_frame) {
+                if sprite.region != *region {
+                    frame_changes.push((ent, *region));
+                }
+            }
+        }
+
+        for (ent, region) in frame_changes {
+            if let Some(sprite) = sprites.get_mut(ent) {
+                sprite.region = region;
+                sprite.pivot_pixels = Point2f::new(
+                    sprite.pivot.x * region.w as f32,
+                    sprite.pivot.y * region.h as f32,
+                );
+            }
+        }
+    }
+}
+
 #[derive(Default)]
-pub struct SpriteRenderSystem;
+pub struct SpriteRenderSystem {
+    // Sprites inserted or changed since the last tick. Nothing is cached between ticks yet, but this is where to look once it is.
+    pub modified_sprites: BitSet,
+    pub sprite_reader_id: Option<ReaderId<ComponentEvent>>,
+}
 
 impl<'a> System<'a> for SpriteRenderSystem {
     type SystemData = (
@@ -182,7 +655,29 @@ impl<'a> System<'a> for SpriteRenderSystem {
         ReadStorage<'a, RigidbodyComponent>,
     );
 
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.sprite_reader_id =
+            Some(WriteStorage::<SpriteComponent>::fetch(&world).register_reader());
+    }
+
     fn run(&mut self, (physics, mut render, transforms, sprites, rigidbodies): Self::SystemData) {
+        let mut render = render.scope();
+
+        // Process SpriteComponent events into a bitset
+        self.modified_sprites.clear();
+        let sprite_events = sprites
+            .channel()
+            .read(self.sprite_reader_id.as_mut().unwrap());
+        for event in sprite_events {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    self.modified_sprites.add(*id);
+                }
+                _ => {}
+            }
+        }
+
         for (transform, sprite, rigidbody) in (&transforms, &sprites, (&rigidbodies).maybe()).join()
         {
             let (x, y) = if let Some(_) = rigidbody {
@@ -197,7 +692,13 @@ impl<'a> System<'a> for SpriteRenderSystem {
 
             render.bind_transparency(sprite.transparency);
             render.bind_texture(sprite.spritesheet_tex_id);
-            render.bind_color(sprite.color);
+            // Both are folded into the vertex color, which the shader already multiplies with the texture
+            render.bind_color(
+                sprite
+                    .color
+                    .tinted(sprite.tint)
+                    .with_alpha(sprite.color.a * sprite.alpha),
+            );
             render.bind_layer(sprite.layer);
             render.sprite(
                 x as f32,
@@ -209,3 +710,161 @@ impl<'a> System<'a> for SpriteRenderSystem {
         }
     }
 }
+
+#[derive(Default)]
+pub struct ColliderDebugRenderSystem;
+
+impl<'a> System<'a> for ColliderDebugRenderSystem {
+    type SystemData = (
+        ReadExpect<'a, DebugState>,
+        Write<'a, RenderState>,
+        ReadStorage<'a, TransformComponent>,
+        ReadStorage<'a, ColliderComponent>,
+    );
+
+    fn run(&mut self, (debug, mut render, transforms, colliders): Self::SystemData) {
+        let mut render = render.scope();
+
+        if !debug.draw_colliders {
+            return;
+        }
+
+        render.bind_layer(layers::Layer::Debug);
+        render.bind_transparency(Transparency::Opaque);
+
+        for (transform, collider) in (&transforms, &colliders).join() {
+            // Outlines are in world units, relative to the collider center
+            let outline: Polyline<f64> = if let Some(cuboid) = collider.shape.as_shape::<Cuboid<f64>>() {
+                cuboid.to_polyline(())
+            } else if let Some(ball) = collider.shape.as_shape::<Ball<f64>>() {
+                ball.to_polyline(16)
+            } else {
+                continue;
+            };
+
+            let center = transform.position + collider.center + collider.offset;
+            let mut points: Vec<(f32, f32)> = outline
+                .coords()
+                .iter()
+                .map(|p| {
+                    let p = world_to_screen(p.coords) + center;
+                    (p.x as f32, p.y as f32)
+                })
+                .collect();
+
+            // Close the outline
+            if let Some(first) = points.first().cloned() {
+                points.push(first);
+            }
+
+            render.line(&points, COLOR_GREEN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(count: u32) -> Vec<SpriteRegion> {
+        (0..count)
+            .map(|i| SpriteRegion {
+                x: i * 16,
+                y: 0,
+                w: 16,
+                h: 16,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn advance_two_frames_at_two_fps() {
+        let mut animation = AnimatedSpriteComponent::new(frames(2), 2.0, true);
+
+        animation.advance(0.25);
+        assert_eq!(animation.current_frame(), 0);
+
+        animation.advance(0.25);
+        assert_eq!(animation.current_frame(), 1);
+
+        // One full second is two frames, so a looping animation ends up where it started
+        animation.advance(1.0);
+        assert_eq!(animation.current_frame(), 1);
+
+        animation.advance(0.5);
+        assert_eq!(animation.current_frame(), 0);
+    }
+
+    #[test]
+    fn advance_stops_on_last_frame_when_not_looping() {
+        let mut animation = AnimatedSpriteComponent::new(frames(2), 2.0, false);
+        assert!(!animation.is_finished());
+
+        animation.advance(0.5);
+        assert_eq!(animation.current_frame(), 1);
+        assert!(animation.is_finished());
+
+        animation.advance(5.0);
+        assert_eq!(animation.current_frame(), 1);
+    }
+
+    #[test]
+    fn nine_slice_rects_split_region_and_rect() {
+        let region = SpriteRegion { x: 10, y: 20, w: 30, h: 40 };
+        let rects = nine_slice_rects(100.0, 200.0, 90.0, 60.0, region, 5);
+
+        let expected = [
+            ((100.0, 200.0, 5.0, 5.0), SpriteRegion { x: 10, y: 20, w: 5, h: 5 }),
+            ((105.0, 200.0, 80.0, 5.0), SpriteRegion { x: 15, y: 20, w: 20, h: 5 }),
+            ((185.0, 200.0, 5.0, 5.0), SpriteRegion { x: 35, y: 20, w: 5, h: 5 }),
+            ((100.0, 205.0, 5.0, 50.0), SpriteRegion { x: 10, y: 25, w: 5, h: 30 }),
+            ((105.0, 205.0, 80.0, 50.0), SpriteRegion { x: 15, y: 25, w: 20, h: 30 }),
+            ((185.0, 205.0, 5.0, 50.0), SpriteRegion { x: 35, y: 25, w: 5, h: 30 }),
+            ((100.0, 255.0, 5.0, 5.0), SpriteRegion { x: 10, y: 55, w: 5, h: 5 }),
+            ((105.0, 255.0, 80.0, 5.0), SpriteRegion { x: 15, y: 55, w: 20, h: 5 }),
+            ((185.0, 255.0, 5.0, 5.0), SpriteRegion { x: 35, y: 55, w: 5, h: 5 }),
+        ];
+        assert_eq!(rects, expected);
+    }
+
+    #[test]
+    fn nine_slice_rects_shrink_border_to_fit() {
+        let region = SpriteRegion { x: 0, y: 0, w: 10, h: 10 };
+        let rects = nine_slice_rects(0.0, 0.0, 6.0, 20.0, region, 8);
+
+        // The source border is capped at half the region, and the destination border at half the rect
+        assert_eq!(rects[0], ((0.0, 0.0, 3.0, 5.0), SpriteRegion { x: 0, y: 0, w: 5, h: 5 }));
+        assert_eq!(rects[4], ((3.0, 5.0, 0.0, 10.0), SpriteRegion { x: 5, y: 5, w: 0, h: 0 }));
+        assert_eq!(rects[8], ((3.0, 15.0, 3.0, 5.0), SpriteRegion { x: 5, y: 5, w: 5, h: 5 }));
+    }
+
+    #[test]
+    fn animation_system_updates_pivot_with_frame() {
+        let mut world = World::new();
+        world.register::<AnimatedSpriteComponent>();
+        world.register::<SpriteComponent>();
+        world.insert::<DeltaTime>(0.5);
+
+        let small = SpriteRegion { x: 0, y: 0, w: 16, h: 16 };
+        let large = SpriteRegion { x: 16, y: 0, w: 32, h: 64 };
+        let ent = world
+            .create_entity()
+            .with(AnimatedSpriteComponent::new(vec![small, large], 2.0, true))
+            .with(SpriteComponent::new(
+                small,
+                0,
+                Point2f::new(0.5, 1.0),
+                COLOR_WHITE,
+                Layer::Lab,
+                Transparency::Opaque,
+            ))
+            .build();
+
+        AnimationSystem.run_now(&world);
+
+        let sprites = world.read_storage::<SpriteComponent>();
+        let sprite = sprites.get(ent).unwrap();
+        assert_eq!(sprite.region, large);
+        assert_eq!(sprite.pivot_pixels, Point2f::new(16.0, 64.0));
+    }
+}
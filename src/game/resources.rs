@@ -5,3 +5,5 @@ pub const TEX_SPRITESHEET_UI: TextureId = 2;
 pub const TEX_FONT: TextureId = 3;
 pub const TEX_BG_LIGHTHOUSE: TextureId = 5;
 pub const TEX_BG_LIGHTHOUSE_LIGHT: TextureId = 6;
+
+pub const ATLAS_SPRITESHEET_UI: &str = "res/textures/ui-sprites.json";
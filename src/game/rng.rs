@@ -0,0 +1,142 @@
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+/// Seeded RNG shared by all game systems, so that a run can be reproduced from its seed.
+///
+/// It also counts how far into its stream it is, so a loaded game can pick the stream up where the save left it,
+/// instead of replaying the rolls from the start of the run.
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+    // How many 32-bit words have been drawn since seeding
+    words_drawn: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            words_drawn: 0,
+        }
+    }
+
+    /// Seed the RNG, and skip ahead to where another one with the same seed was after drawing `words_drawn` words.
+    pub fn resume(seed: u64, words_drawn: u64) -> Self {
+        let mut rng = GameRng::new(Some(seed));
+        for _ in 0..words_drawn {
+            rng.next_u32();
+        }
+
+        rng
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn words_drawn(&self) -> u64 {
+        self.words_drawn
+    }
+}
+
+// StdRng hands out its stream one 32-bit word at a time. A u64 takes two words, and bytes are filled from whole
+// words, with the rest of the last one thrown away.
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.words_drawn += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.words_drawn += 2;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.words_drawn += ((dest.len() + 3) / 4) as u64;
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.words_drawn += ((dest.len() + 3) / 4) as u64;
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::*;
+    use gfx::{input::InputState, window::DeltaTime};
+
+    // Run a game for some times of day, and return the last happening after each one
+    fn happening_ids(seed: u64, times_of_day: usize) -> Vec<Option<i32>> {
+        let mut state = GameState::new(1280, 720, Some(seed));
+        state.world.insert(InputState::new());
+        state.world.insert::<DeltaTime>(1.0 / 60.0);
+
+        let mut ids = Vec::new();
+        for _ in 0..times_of_day {
+            state
+                .world
+                .write_resource::<EventChannel<GameEvent>>()
+                .single_write(GameEvent::ProgressTime { hours: HOURS_PER_TIME_OF_DAY });
+
+            // Once for the time system to start the new time of day, and once more to be sure everything saw it
+            state.tick_dispatcher.dispatch(&state.world);
+            state.tick_dispatcher.dispatch(&state.world);
+            state.world.maintain();
+
+            ids.push(state.world.read_resource::<ActivityState>().last_happening_id);
+        }
+
+        ids
+    }
+
+    #[test]
+    fn same_seed_gives_same_happenings() {
+        let a = happening_ids(1234, 12);
+        let b = happening_ids(1234, 12);
+
+        assert!(a.iter().any(|id| id.is_some()));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let mut a = GameRng::new(Some(1234));
+        let mut b = GameRng::new(Some(1234));
+
+        for _ in 0..32 {
+            assert_eq!(a.gen_range(0, 1000), b.gen_range(0, 1000));
+        }
+        assert_eq!(a.seed(), b.seed());
+    }
+
+    #[test]
+    fn resume_continues_the_stream() {
+        let mut original = GameRng::new(Some(99));
+        original.next_u32();
+        original.next_u64();
+        let mut bytes = [0u8; 7];
+        original.fill_bytes(&mut bytes);
+        assert_eq!(original.words_drawn(), 5);
+
+        let mut resumed = GameRng::resume(original.seed(), original.words_drawn());
+        for _ in 0..16 {
+            assert_eq!(resumed.next_u64(), original.next_u64());
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_sequences() {
+        let mut a = GameRng::new(Some(1));
+        let mut b = GameRng::new(Some(2));
+
+        let a_values: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let b_values: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        assert_ne!(a_values, b_values);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::game::{activity::{ActivityState, Cooldown}, merchant::MerchantState, rng::GameRng, stats::StatsState, time::TimeState, weather::WeatherState};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// The subset of the world that makes up a run's progress.
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    pub time: TimeState,
+    pub stats: StatsState,
+    pub merchant: MerchantState,
+    pub last_happening_id: Option<i32>,
+    #[serde(default)]
+    pub weather: WeatherState,
+    #[serde(default)]
+    pub activity_cooldowns: HashMap<String, Cooldown>,
+    // Saves from before the seed was kept don't have one, and get a new random seed
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // How far into the seed's stream the run was, so loading carries on from there instead of from the start
+    #[serde(default)]
+    pub rng_words_drawn: u64,
+}
+
+impl SaveData {
+    pub fn from_world(world: &World) -> Self {
+        SaveData {
+            time: world.read_resource::<TimeState>().clone(),
+            stats: world.read_resource::<StatsState>().clone(),
+            merchant: world.read_resource::<MerchantState>().clone(),
+            last_happening_id: world.read_resource::<ActivityState>().last_happening_id,
+            weather: world.read_resource::<WeatherState>().clone(),
+            activity_cooldowns: world.read_resource::<ActivityState>().cooldowns.clone(),
+            seed: Some(world.read_resource::<GameRng>().seed()),
+            rng_words_drawn: world.read_resource::<GameRng>().words_drawn(),
+        }
+    }
+
+    pub fn apply_to_world(self, world: &mut World) {
+        world.insert(self.time);
+        world.insert(self.stats);
+        world.insert(self.merchant);
+        world.insert(self.weather);
+        if let Some(seed) = self.seed {
+            world.insert(GameRng::resume(seed, self.rng_words_drawn));
+        }
+
+        let mut activity_state = world.write_resource::<ActivityState>();
+        activity_state.last_happening_id = self.last_happening_id;
+        activity_state.cooldowns = self.activity_cooldowns;
+        activity_state.is_rebuild_required = true;
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn read(path: &str) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        let data = serde_json::from_str(&json)?;
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::*;
+    use rand::RngCore;
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut state = GameState::new(1280, 720, Some(42));
+        {
+            let mut time = state.world.write_resource::<TimeState>();
+            time.day = 3;
+            time.time_of_day = TimeOfDay::Afternoon;
+            time.hours_passed = 2;
+
+            let mut stats = state.world.write_resource::<StatsState>();
+            stats.add(Stat::Food, -3);
+            stats.add(Stat::Money, 7);
+            stats.set_condition(GameCondition::LensBroken, true);
+        }
+
+        let path = std::env::temp_dir().join("lighthouse-keeper-save-test.json");
+        let path = path.to_str().unwrap();
+        state.save(path).unwrap();
+        let loaded = GameState::load(path, 1280, 720).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let time = loaded.world.read_resource::<TimeState>();
+        assert_eq!(time.day, 3);
+        assert!(time.time_of_day == TimeOfDay::Afternoon);
+        assert_eq!(time.hours_passed, 2);
+
+        let stats = loaded.world.read_resource::<StatsState>();
+        assert_eq!(stats.stat(Stat::Food), 5);
+        assert_eq!(stats.stat(Stat::Money), 12);
+        assert!(stats.condition(GameCondition::LensBroken));
+
+        assert_eq!(loaded.world.read_resource::<GameRng>().seed(), 42);
+
+        // The next roll after loading is the one the original run would have made next
+        let mut original_rng = state.world.write_resource::<GameRng>();
+        let mut loaded_rng = loaded.world.write_resource::<GameRng>();
+        assert!(original_rng.words_drawn() > 0);
+        assert_eq!(loaded_rng.words_drawn(), original_rng.words_drawn());
+        assert_eq!(loaded_rng.next_u64(), original_rng.next_u64());
+        assert!(loaded.world.read_resource::<ActivityState>().is_rebuild_required);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::game::{audio::AudioCategory, render::TextAlign, *};
+use gfx::{input::*, Point2d};
+use serde::{Deserialize, Serialize};
+
+// How much one click of a volume button changes the volume
+const VOLUME_STEP: f32 = 0.1;
+
+// Size of the area around a volume button that counts as clicking it
+const VOLUME_BUTTON_SIZE: f32 = 20.0;
+
+/// Player preferences. These aren't part of a run, so they're kept in their own file next to the save.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Settings {
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+        }
+    }
+
+    /// The volume to play a sound in the given category at, including the master volume.
+    pub fn volume(&self, category: AudioCategory) -> f32 {
+        let category_volume = match category {
+            AudioCategory::Music => self.music_volume,
+            AudioCategory::Sfx => self.sfx_volume,
+        };
+
+        self.master_volume * category_volume
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn read(path: &str) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        let settings = serde_json::from_str(&json)?;
+
+        Ok(settings)
+    }
+
+    fn volume_mut(&mut self, row: usize) -> &mut f32 {
+        match row {
+            0 => &mut self.master_volume,
+            1 => &mut self.music_volume,
+            _ => &mut self.sfx_volume,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::new()
+    }
+}
+
+const VOLUME_LABELS: [&str; 3] = ["Master", "Music", "Effects"];
+
+// Where each row of the volume controls goes, and the centers of its - and + buttons.
+// The controls sit under the pause text, in the middle of the screen.
+fn volume_row_layout(row: usize, screen_width: u32, screen_height: u32) -> (f32, (f32, f32), (f32, f32)) {
+    let center_x = screen_width as f32 / 2.0;
+    let y = (screen_height as f32 / 2.0) + 32.0 + (row as f32 * 24.0);
+    let button_y = y + 8.0;
+
+    (y, (center_x + 16.0, button_y), (center_x + 112.0, button_y))
+}
+
+/// Change the volumes if one of the volume buttons was clicked. Returns true if a volume changed.
+pub fn handle_volume_controls(settings: &mut Settings, input: &InputState, cursor: Point2d, screen_width: u32, screen_height: u32) -> bool {
+    if !input.is_mouse_button_pressed(MouseButton::Left) {
+        return false;
+    }
+
+    let is_over = |(x, y): (f32, f32)| {
+        let half_size = (VOLUME_BUTTON_SIZE / 2.0) as f64;
+        ((cursor.x - x as f64).abs() <= half_size) && ((cursor.y - y as f64).abs() <= half_size)
+    };
+
+    for row in 0..VOLUME_LABELS.len() {
+        let (_, minus, plus) = volume_row_layout(row, screen_width, screen_height);
+        let step = if is_over(minus) {
+            -VOLUME_STEP
+        } else if is_over(plus) {
+            VOLUME_STEP
+        } else {
+            continue;
+        };
+
+        let volume = settings.volume_mut(row);
+        *volume = (*volume + step).max(0.0).min(1.0);
+
+        return true;
+    }
+
+    false
+}
+
+/// Draw the volume controls. Binds its own color, layer, and texture.
+pub fn draw_volume_controls(render: &mut RenderState, settings: &Settings, screen_width: u32, screen_height: u32) {
+    let volumes = [settings.master_volume, settings.music_volume, settings.sfx_volume];
+
+    render.bind_color(COLOR_WHITE);
+    render.bind_layer(layers::Layer::Overlay);
+    render.bind_texture(resources::TEX_FONT);
+
+    for (row, (label, volume)) in VOLUME_LABELS.iter().zip(volumes.iter()).enumerate() {
+        let (y, minus, plus) = volume_row_layout(row, screen_width, screen_height);
+        let center_x = screen_width as f32 / 2.0;
+
+        render.text_aligned(center_x - 16.0, y, 8, 16, 1.0, TextAlign::Right, label);
+        render.text_aligned(minus.0, y, 8, 16, 1.0, TextAlign::Center, "-");
+        render.text_aligned((minus.0 + plus.0) / 2.0, y, 8, 16, 1.0, TextAlign::Center, &format!("{}%", (volume * 100.0).round() as i32));
+        render.text_aligned(plus.0, y, 8, 16, 1.0, TextAlign::Center, "+");
+    }
+}
@@ -1,7 +1,17 @@
-use crate::game::*;
+use crate::game::{rng::GameRng, *};
 use specs::prelude::*;
 use std::collections::HashMap;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// How many days a tank of gas runs the generator for
+const GENERATOR_DAYS_PER_TANK: i32 = 2;
+
+// Money earned for each night the lighthouse is kept on
+const PAY_PER_NIGHT: i32 = 2;
+
+// How many days between paychecks
+const DAYS_PER_PAYCHECK: i32 = 5;
 
 #[derive(Clone, Copy, Debug)]
 pub enum ConditionEffect {
@@ -15,7 +25,7 @@ pub enum StatEffect {
     Subtract { stat: Stat, amount: i32 },
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Stat {
     Sanity,
     Food,
@@ -39,10 +49,23 @@ impl std::fmt::Display for Stat {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameOverReason {
+    Starved,
+    Insane,
+    FinalDay,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct StatsState {
     stats: HashMap<Stat, i32>,
     conditions: HashMap<GameCondition, bool>,
     pub money_earned: i32,
+    pub total_money_earned: i32,
+    pub game_over_reason: Option<GameOverReason>,
+    // Min and max value of each stat. This is config rather than state, so it isn't saved.
+    #[serde(skip, default = "default_stat_bounds")]
+    pub stat_bounds: HashMap<Stat, (i32, i32)>,
 }
 
 impl StatsState {
@@ -56,7 +79,14 @@ impl StatsState {
 
         let conditions = HashMap::new();
 
-        StatsState { stats, conditions, money_earned: 0, }
+        StatsState {
+            stats,
+            conditions,
+            money_earned: 0,
+            total_money_earned: 0,
+            game_over_reason: None,
+            stat_bounds: default_stat_bounds(),
+        }
     }
 
     pub fn condition(&self, condition: GameCondition) -> bool {
@@ -68,13 +98,186 @@ impl StatsState {
         *entry = val;
     }
 
+    /// The conditions that are currently set, in declaration order.
+    pub fn active_conditions(&self) -> Vec<GameCondition> {
+        let mut conditions: Vec<GameCondition> = self
+            .conditions
+            .iter()
+            .filter(|(_, is_set)| **is_set)
+            .map(|(condition, _)| *condition)
+            .collect();
+
+        conditions.sort_by_key(|condition| *condition as u8);
+        conditions
+    }
+
+    /// Whether the lighthouse light can run. Both the light at night and the keeper's pay depend on this.
+    pub fn is_lighthouse_working(&self) -> bool {
+        !self.condition(GameCondition::LensBroken)
+            && !self.condition(GameCondition::GeneratorBroken)
+            && !self.condition(GameCondition::GeneratorOutOfGas)
+    }
+
+    pub fn set_game_over(&mut self, reason: GameOverReason) {
+        self.set_condition(GameCondition::GameOver, true);
+        self.game_over_reason = Some(reason);
+    }
+
+    /// End the game as a win, for keeping the light until the final day.
+    pub fn set_won(&mut self) {
+        self.set_condition(GameCondition::Won, true);
+        self.set_game_over(GameOverReason::FinalDay);
+    }
+
     pub fn stat(&self, stat: Stat) -> i32 {
         self.stats.get(&stat).unwrap_or(&0).clone()
     }
 
+    /// Add to a stat, saturating at its bounds.
     pub fn add(&mut self, stat: Stat, amount: i32) {
+        let (min, max) = self
+            .stat_bounds
+            .get(&stat)
+            .cloned()
+            .unwrap_or((std::i32::MIN, std::i32::MAX));
+
         let entry = self.stats.entry(stat).or_insert(0);
-        *entry += amount;
+        *entry = entry.saturating_add(amount).max(min).min(max);
+    }
+
+    /// Add last night's pay to the next paycheck, if the lighthouse was working.
+    /// Returns false if the pay was docked.
+    pub fn accrue_pay(&mut self) -> bool {
+        if !self.is_lighthouse_working() {
+            return false;
+        }
+
+        self.money_earned += PAY_PER_NIGHT;
+        self.total_money_earned += PAY_PER_NIGHT;
+        true
+    }
+
+    /// Pay out everything earned since the last paycheck, if the given day is a payday.
+    /// Returns the amount paid, or None if it isn't a payday.
+    pub fn collect_paycheck(&mut self, day: i32) -> Option<i32> {
+        if (day % DAYS_PER_PAYCHECK) != 0 {
+            return None;
+        }
+
+        let amt = self.money_earned;
+        self.add(Stat::Money, amt);
+        self.money_earned = 0;
+
+        Some(amt)
+    }
+
+    /// Apply the start of a new day: pay, the final day, the generator's gas, food rations, starvation, and sanity.
+    /// Log messages are passed to `log`, and any game events that should be sent are returned.
+    pub fn process_new_day(&mut self, day: i32, final_day: i32, log: &mut impl FnMut(LogEvent)) -> Vec<GameEvent> {
+        // If the lighthouse wasn't broken, add money to this paycheck
+        if !self.accrue_pay() {
+            log(LogEvent { message: String::from("The lighthouse wasn't on last night! Your pay will be docked."), color: COLOR_YELLOW, reveal: false });
+        }
+
+        match self.collect_paycheck(day) {
+            Some(0) => {
+                log(LogEvent { message: String::from("You didn't get a paycheck this week because the lighthouse has not been on."), color: COLOR_RED, reveal: false });
+            }
+            Some(amt) => {
+                log(LogEvent { message: format!("You receive a paycheck for your duties. (Money +{})", amt), color: COLOR_GREEN, reveal: false });
+            }
+            None => {}
+        }
+
+        self.set_condition(GameCondition::FinalDay, day >= final_day);
+
+        // The generator burns through a tank of gas every few days, and has to be refueled.
+        // This is checked after pay, so running dry this morning doesn't dock last night's pay.
+        if ((day % GENERATOR_DAYS_PER_TANK) == 0) && !self.condition(GameCondition::GeneratorOutOfGas) {
+            self.set_condition(GameCondition::GeneratorOutOfGas, true);
+            log(LogEvent { message: String::from("The generator sputters and dies. It's out of gas."), color: COLOR_RED, reveal: false });
+        }
+
+        // Handle food consumption
+        if !self.condition(GameCondition::Starving) {
+            if self.stat(Stat::Food) <= 0 {
+                self.set_condition(GameCondition::Starving, true);
+                log(LogEvent { message: String::from("You are starving."), color: COLOR_RED, reveal: false });
+            } else {
+                log(LogEvent { message: String::from("You unpack the days rations from food storage. (Food -1)"), color: COLOR_BLACK, reveal: false });
+                self.add(Stat::Food, -1);
+            }
+        } else {
+            if self.stat(Stat::Food) <= 0 {
+                log(LogEvent { message: String::from("You collapse due to starvation."), color: COLOR_RED, reveal: false });
+                self.set_game_over(GameOverReason::Starved);
+                return vec![GameEvent::RefreshActivities];
+            }
+
+            self.set_condition(GameCondition::Starving, false);
+        }
+
+        // Handle sanity
+        if !self.condition(GameCondition::Insane) {
+            if self.stat(Stat::Sanity) <= 0 {
+                self.set_condition(GameCondition::Insane, true);
+                log(LogEvent { message: String::from("You can't make the voices stop."), color: COLOR_BLUE, reveal: false });
+            }
+        } else {
+            if self.stat(Stat::Sanity) <= 0 {
+                log(LogEvent { message: String::from("In a fit of insanity, you throw yourself from atop the lighthouse."), color: COLOR_RED, reveal: false });
+                self.set_game_over(GameOverReason::Insane);
+                return vec![GameEvent::RefreshActivities];
+            }
+
+            self.set_condition(GameCondition::Insane, false);
+        }
+
+        vec![]
+    }
+}
+
+fn default_stat_bounds() -> HashMap<Stat, (i32, i32)> {
+    let mut bounds = HashMap::new();
+    bounds.insert(Stat::Sanity, (0, 10));
+    bounds.insert(Stat::Food, (0, 20));
+    bounds.insert(Stat::Gas, (0, 20));
+    bounds.insert(Stat::Parts, (0, 20));
+    // Money has no cap, so pay is never thrown away
+    bounds.insert(Stat::Money, (0, std::i32::MAX));
+    bounds
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FishingResult {
+    HugeFish,
+    Fish,
+    NoBites,
+}
+
+/// Roll a fishing trip. Fish bite best in the morning and worst at night, and rough weather keeps them away.
+pub fn fishing_outcome<R: Rng + ?Sized>(rng: &mut R, time_of_day: TimeOfDay, weather: &WeatherState) -> FishingResult {
+    let (mut huge_chance, mut fish_chance) = match time_of_day {
+        TimeOfDay::Morning => (0.01, 0.45),
+        TimeOfDay::Afternoon => (0.005, 0.3),
+        TimeOfDay::Night => (0.005, 0.2),
+    };
+
+    let weather_modifier = match weather.kind {
+        Weather::Clear | Weather::Cloudy => 1.0,
+        Weather::Fog => 0.8,
+        Weather::Storm => 1.0 - (0.5 * weather.intensity),
+    };
+    huge_chance *= weather_modifier;
+    fish_chance *= weather_modifier;
+
+    let roll: f32 = rng.gen();
+    if roll < huge_chance {
+        FishingResult::HugeFish
+    } else if roll <= fish_chance {
+        FishingResult::Fish
+    } else {
+        FishingResult::NoBites
     }
 }
 
@@ -85,9 +288,14 @@ pub struct StatsSystem {
 
 impl<'a> System<'a> for StatsSystem {
     type SystemData = (
-        ReadExpect<'a, EventChannel<GameEvent>>,
+        WriteExpect<'a, EventChannel<GameEvent>>,
         WriteExpect<'a, StatsState>,
+        WriteExpect<'a, GameRng>,
         WriteExpect<'a, EventChannel<LogEvent>>,
+        ReadExpect<'a, GameConfig>,
+        ReadExpect<'a, TimeState>,
+        ReadExpect<'a, WeatherState>,
+        ReadExpect<'a, DebugState>,
     );
 
     fn setup(&mut self, world: &mut World) {
@@ -100,117 +308,117 @@ impl<'a> System<'a> for StatsSystem {
         );
     }
 
-    fn run(&mut self, (game_events, mut stats, mut log_events): Self::SystemData) {
-        // TODO
-        // every 2 days, consume gasoline and flag generator as empty
-
+    fn run(&mut self, (mut game_events, mut stats, mut rng, mut log_events, config, time, weather, debug): Self::SystemData) {
+        // Events to send once the reader is done, like the stat effects of a fishing trip
+        let mut queued_events = Vec::new();
         for event in game_events.read(&mut self.game_event_reader.as_mut().unwrap()) {
             match event {
-                GameEvent::GameOver => {
-                    stats.set_condition(GameCondition::GameOver, true);
+                GameEvent::Victory => {
+                    stats.set_won();
+                }
+                GameEvent::NewGameStarted => {
+                    *stats = StatsState::new();
                 }
                 GameEvent::NewDayStarted { day } => {
-                    // If the lighthouse wasn't broken, add money to this paycheck
-                    if stats.condition(GameCondition::LensBroken) || stats.condition(GameCondition::GeneratorBroken) {
-                        log_events.single_write(LogEvent { message: String::from("The lighthouse wasn't on last night! Your pay will be docked."), color: COLOR_YELLOW });
-                    } else {
-                        stats.money_earned += 2;
-                    }
-
-                    if (day % 5) == 0 {
-                        let amt = stats.money_earned;
-                        stats.add(Stat::Money, amt);
-
-                        if amt == 0 {
-                            log_events.single_write(LogEvent { message: String::from("You didn't get a paycheck this week because the lighthouse has not been on."), color: COLOR_RED });
-                        } else {
-                            log_events.single_write(LogEvent { message: format!("You receive a paycheck for your duties. (Money +{})", amt), color: COLOR_GREEN });
+                    let mut log = |event| log_events.single_write(event);
+                    queued_events.extend(stats.process_new_day(*day, config.final_day, &mut log));
+                }
+                GameEvent::HandleStatEffects { effects } => {
+                    // Net change of each stat, in the order they were first affected
+                    let mut changes: Vec<(Stat, i32)> = Vec::new();
+                    for effect in effects {
+                        let (stat, amount) = match effect {
+                            StatEffect::Add { stat, amount } => (*stat, *amount),
+                            StatEffect::Subtract { stat, amount } => (*stat, -*amount),
+                        };
+
+                        stats.add(stat, amount);
+                        if debug.print_stat_effects {
+                            println!("({} {}{})", stat, if amount < 0 { "-" } else { "+" }, amount.abs());
                         }
 
-                        stats.money_earned = 0;
-                    }
-
-                    stats.set_condition(GameCondition::FinalDay, *day >= 30);
-
-                    // Handle food consumption
-                    if !stats.condition(GameCondition::Starving) {
-                        if stats.stat(Stat::Food) <= 0 {
-                            stats.set_condition(GameCondition::Starving, true);
-                            log_events.single_write(LogEvent { message: String::from("You are starving."), color: COLOR_RED });
-                        } else {
-                            log_events.single_write(LogEvent { message: String::from("You unpack the days rations from food storage. (Food -1)"), color: COLOR_BLACK });
-                            stats.add(Stat::Food, -1);
-                        }
-                    } else {
-                        if stats.stat(Stat::Food) <= 0 {
-                            log_events.single_write(LogEvent { message: String::from("You collapse due to starvation."), color: COLOR_RED });
-                            stats.set_condition(GameCondition::GameOver, true);
-                            continue;
+                        match changes.iter_mut().find(|(s, _)| *s == stat) {
+                            Some((_, net)) => *net += amount,
+                            None => changes.push((stat, amount)),
                         }
+                    }
 
-                        stats.set_condition(GameCondition::Starving, false);
+                    changes.retain(|(_, net)| *net != 0);
+                    if changes.is_empty() {
+                        continue;
                     }
 
-                    // Handle sanity
-                    if !stats.condition(GameCondition::Insane) {
-                        if stats.stat(Stat::Sanity) <= 0 {
-                            stats.set_condition(GameCondition::Insane, true);
-                            println!("You can't make the voices stop.");
-                            log_events.single_write(LogEvent { message: String::from("You can't make the voices stop."), color: COLOR_BLUE });
-                        }
+                    let summary = changes
+                        .iter()
+                        .map(|(stat, net)| format!("{} {}{}", stat, if *net < 0 { "-" } else { "+" }, net.abs()))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+
+                    let total: i32 = changes.iter().map(|(_, net)| net).sum();
+                    let color = if total > 0 {
+                        COLOR_GREEN
+                    } else if total < 0 {
+                        COLOR_RED
                     } else {
-                        if stats.stat(Stat::Sanity) <= 0 {
-                            log_events.single_write(LogEvent { message: String::from("In a fit of insanity, you throw yourself from atop the lighthouse."), color: COLOR_RED });
-                            stats.set_condition(GameCondition::GameOver, true);
-                            continue;
-                        }
+                        COLOR_BLACK
+                    };
 
-                        stats.set_condition(GameCondition::Insane, false);
-                    }
-                }
-                GameEvent::HandleStatEffects { effects } => {
-                    for effect in effects {
-                        match effect {
-                            StatEffect::Add { stat, amount } => {
-                                stats.add(*stat, *amount);
-                                println!("({} +{})", stat, amount.abs());
-                            }
-                            StatEffect::Subtract { stat, amount } => {
-                                stats.add(*stat, -*amount);
-                                println!("({} -{})", stat, amount.abs());
-                            }
-                        }
-                    }
+                    log_events.single_write(LogEvent { message: summary, color, reveal: false });
                 }
                 GameEvent::HandleConditionEffects { effects } => {
                     for effect in effects {
                         match effect {
                             ConditionEffect::Set { condition } => {
                                 stats.set_condition(*condition, true);
-                                println!("SET {:?}", condition);
+                                println!("SET {}", condition);
                             }
                             ConditionEffect::Clear { condition } => {
                                 stats.set_condition(*condition, false);
-                                println!("CLEAR {:?}", condition);
+                                println!("CLEAR {}", condition);
                             }
                         }
                     }
                 }
-                GameEvent::ActivityGoFishing => {
-                    let roll: f32 = rand::thread_rng().gen();
-                    if roll < 0.005 {
-                        log_events.single_write(LogEvent { message: String::from("You catch a huge fish! (Food +2)"), color: COLOR_GREEN });
-                        stats.add(Stat::Food, 2);
-                    } else if roll <= 0.3 {
-                        log_events.single_write(LogEvent { message: String::from("You catch a fish. (Food +1)"), color: COLOR_BLACK });
-                        stats.add(Stat::Food, 1);
-                    } else {
-                        log_events.single_write(LogEvent { message: String::from("You try to catch a fish, but get no bites."), color: COLOR_RED });
+                GameEvent::ActivityGoFishing => match fishing_outcome(&mut *rng, time.time_of_day, &weather) {
+                    FishingResult::HugeFish => {
+                        log_events.single_write(LogEvent { message: String::from("You catch a huge fish! (Food +2)"), color: COLOR_GREEN, reveal: false });
+                        queued_events.push(GameEvent::HandleStatEffects {
+                            effects: vec![StatEffect::Add { stat: Stat::Food, amount: 2 }],
+                        });
                     }
-                }
+                    FishingResult::Fish => {
+                        log_events.single_write(LogEvent { message: String::from("You catch a fish. (Food +1)"), color: COLOR_BLACK, reveal: false });
+                        queued_events.push(GameEvent::HandleStatEffects {
+                            effects: vec![StatEffect::Add { stat: Stat::Food, amount: 1 }],
+                        });
+                    }
+                    FishingResult::NoBites => {
+                        let msg = if weather.kind == Weather::Storm {
+                            "The storm keeps the fish deep, and you get no bites."
+                        } else {
+                            "You try to catch a fish, but get no bites."
+                        };
+                        log_events.single_write(LogEvent { message: String::from(msg), color: COLOR_RED, reveal: false });
+                    }
+                },
                 _ => {}
             }
         }
+
+        game_events.iter_write(queued_events);
+    }
+}
+
+/// Where a stat's icon is drawn. Money has no icon, so this is the right edge of the money text instead.
+pub fn stat_icon_position(stat: Stat, screen_width: u32) -> (f32, f32) {
+    // Anchor the stats to the right edge of the screen
+    let icon_pos_x = screen_width as f32 - 55.0;
+    match stat {
+        Stat::Sanity => (icon_pos_x, 10.0),
+        Stat::Food => (icon_pos_x, 85.0),
+        Stat::Parts => (icon_pos_x, 150.0),
+        Stat::Gas => (icon_pos_x, 215.0),
+        Stat::Money => (230.0, 8.0),
     }
 }
 
@@ -218,71 +426,40 @@ impl<'a> System<'a> for StatsSystem {
 pub struct StatsInfoRenderSystem;
 
 impl<'a> System<'a> for StatsInfoRenderSystem {
-    type SystemData = (Write<'a, RenderState>, ReadExpect<'a, StatsState>);
+    type SystemData = (
+        Write<'a, RenderState>,
+        ReadExpect<'a, StatsState>,
+        ReadExpect<'a, ScreenState>,
+        ReadExpect<'a, SpriteAtlas>,
+    );
+
+    fn run(&mut self, (mut render, stats, screen, atlas): Self::SystemData) {
+        let mut render = render.scope();
 
-    fn run(&mut self, (mut render, stats): Self::SystemData) {
-        let icon_pos_x = 1225.0;
-        // Sanity icon
         render.bind_transparency(Transparency::Opaque);
         render.bind_texture(resources::TEX_SPRITESHEET_UI);
         render.bind_color(COLOR_WHITE);
-        render.bind_layer(layers::LAYER_UI);
-        render.sprite(
-            icon_pos_x,
-            10.0,
-            Point2f::new(0.0, 0.0),
-            Vector2f::new(0.5, 0.5),
-            SpriteRegion {
-                x: 608,
-                y: 0,
-                w: 96,
-                h: 96,
-            },
-        );
-
-        // Food icon
-        render.sprite(
-            icon_pos_x,
-            85.0,
-            Point2f::new(0.0, 0.0),
-            Vector2f::new(0.5, 0.5),
-            SpriteRegion {
-                x: 704,
-                y: 0,
-                w: 96,
-                h: 96,
-            },
-        );
-
-        // Parts icon
-        render.sprite(
-            icon_pos_x,
-            150.0,
-            Point2f::new(0.0, 0.0),
-            Vector2f::new(0.5, 0.5),
-            SpriteRegion {
-                x: 800,
-                y: 0,
-                w: 96,
-                h: 96,
-            },
-        );
-
-        // Gas icon
-        render.sprite(
-            icon_pos_x,
-            215.0,
-            Point2f::new(0.0, 0.0),
-            Vector2f::new(0.5, 0.5),
-            SpriteRegion {
-                x: 896,
-                y: 0,
-                w: 96,
-                h: 96,
-            },
-        );
+        render.bind_layer(layers::Layer::Ui);
+
+        let icons = [
+            (Stat::Sanity, "sanity_icon"),
+            (Stat::Food, "food_icon"),
+            (Stat::Parts, "parts_icon"),
+            (Stat::Gas, "gas_icon"),
+        ];
+
+        for (stat, region_name) in icons.iter() {
+            let (x, y) = stat_icon_position(*stat, screen.width);
+            render.sprite(
+                x,
+                y,
+                Point2f::new(0.0, 0.0),
+                Vector2f::new(0.5, 0.5),
+                atlas.region(region_name),
+            );
+        }
 
-        let text_pos_x = 1240.0;
+        let text_pos_x = screen.width as f32 - 40.0;
 
         // Sanity text
         render.bind_texture(resources::TEX_FONT);
@@ -326,14 +503,277 @@ impl<'a> System<'a> for StatsInfoRenderSystem {
             &format!("{}", stats.stat(Stat::Gas)),
         );
 
-        // Money text
-        render.text(
-            165.0,
+        // Money text, right aligned so it doesn't shift as the value grows
+        render.text_aligned(
+            230.0,
             8.0,
             8,
             16,
             2.0,
+            TextAlign::Right,
             &format!("${}", stats.stat(Stat::Money)),
         );
     }
 }
+
+#[derive(Default)]
+pub struct ConditionsInfoRenderSystem;
+
+impl<'a> System<'a> for ConditionsInfoRenderSystem {
+    type SystemData = (
+        Write<'a, RenderState>,
+        ReadExpect<'a, StatsState>,
+        ReadExpect<'a, ScreenState>,
+    );
+
+    fn run(&mut self, (mut render, stats, screen): Self::SystemData) {
+        let mut render = render.scope();
+
+        // Listed under the stats, anchored to the right edge of the screen
+        let pos_x = screen.width as f32 - 16.0;
+        let mut pos_y = 310.0;
+
+        render.bind_transparency(Transparency::Opaque);
+        render.bind_texture(resources::TEX_FONT);
+        render.bind_layer(layers::Layer::Ui);
+
+        for condition in stats.active_conditions() {
+            let color = match condition {
+                // The game over screen already covers these
+                GameCondition::GameOver | GameCondition::Won => continue,
+                GameCondition::Inspired => COLOR_GREEN,
+                GameCondition::FinalDay => COLOR_BLACK,
+                _ => COLOR_RED,
+            };
+
+            render.bind_color(color);
+            render.text_aligned(pos_x, pos_y, 8, 16, 1.0, TextAlign::Right, &format!("{}", condition));
+            pos_y += 20.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starting_values() {
+        let stats = StatsState::new();
+
+        assert_eq!(stats.stat(Stat::Sanity), 10);
+        assert_eq!(stats.stat(Stat::Food), 8);
+        assert_eq!(stats.stat(Stat::Gas), 8);
+        assert_eq!(stats.stat(Stat::Parts), 5);
+        assert_eq!(stats.stat(Stat::Money), 5);
+        assert_eq!(stats.money_earned, 0);
+        assert_eq!(stats.total_money_earned, 0);
+        assert!(stats.game_over_reason.is_none());
+    }
+
+    #[test]
+    fn add_negative_amount_subtracts() {
+        let mut stats = StatsState::new();
+        stats.add(Stat::Food, -3);
+
+        assert_eq!(stats.stat(Stat::Food), 5);
+    }
+
+    #[test]
+    fn add_saturates_at_bounds() {
+        let mut stats = StatsState::new();
+        stats.add(Stat::Food, -100);
+        stats.add(Stat::Sanity, 100);
+
+        assert_eq!(stats.stat(Stat::Food), 0);
+        assert_eq!(stats.stat(Stat::Sanity), 10);
+    }
+
+    #[test]
+    fn money_is_not_capped() {
+        let mut stats = StatsState::new();
+        stats.add(Stat::Money, 5000);
+        assert_eq!(stats.stat(Stat::Money), 5005);
+
+        stats.add(Stat::Money, std::i32::MAX);
+        assert_eq!(stats.stat(Stat::Money), std::i32::MAX);
+    }
+
+    #[test]
+    fn condition_defaults_to_false() {
+        let stats = StatsState::new();
+
+        assert!(!stats.condition(GameCondition::Starving));
+        assert!(!stats.condition(GameCondition::GameOver));
+        assert!(stats.active_conditions().is_empty());
+    }
+
+    #[test]
+    fn set_condition() {
+        let mut stats = StatsState::new();
+        stats.set_condition(GameCondition::Dread, true);
+        assert!(stats.condition(GameCondition::Dread));
+
+        stats.set_condition(GameCondition::Dread, false);
+        assert!(!stats.condition(GameCondition::Dread));
+    }
+
+    #[test]
+    fn pay_accrues_while_lighthouse_works() {
+        let mut stats = StatsState::new();
+
+        assert!(stats.accrue_pay());
+        assert!(stats.accrue_pay());
+        assert_eq!(stats.money_earned, PAY_PER_NIGHT * 2);
+        assert_eq!(stats.total_money_earned, PAY_PER_NIGHT * 2);
+    }
+
+    #[test]
+    fn pay_docked_when_lighthouse_broken() {
+        for condition in [GameCondition::LensBroken, GameCondition::GeneratorBroken, GameCondition::GeneratorOutOfGas].iter() {
+            let mut stats = StatsState::new();
+            stats.set_condition(*condition, true);
+
+            assert!(!stats.accrue_pay());
+            assert_eq!(stats.money_earned, 0);
+            assert_eq!(stats.total_money_earned, 0);
+        }
+    }
+
+    #[test]
+    fn paycheck_only_on_payday() {
+        let mut stats = StatsState::new();
+        stats.accrue_pay();
+
+        assert_eq!(stats.collect_paycheck(DAYS_PER_PAYCHECK - 1), None);
+        assert_eq!(stats.stat(Stat::Money), 5);
+
+        assert_eq!(stats.collect_paycheck(DAYS_PER_PAYCHECK), Some(PAY_PER_NIGHT));
+        assert_eq!(stats.stat(Stat::Money), 5 + PAY_PER_NIGHT);
+        assert_eq!(stats.money_earned, 0);
+    }
+
+    #[test]
+    fn paycheck_empty_after_docked_week() {
+        let mut stats = StatsState::new();
+        stats.set_condition(GameCondition::LensBroken, true);
+        for _ in 0..DAYS_PER_PAYCHECK {
+            stats.accrue_pay();
+        }
+
+        assert_eq!(stats.collect_paycheck(DAYS_PER_PAYCHECK), Some(0));
+        assert_eq!(stats.stat(Stat::Money), 5);
+    }
+
+    // Runs a new day, returning the log messages and whether the game ended
+    fn new_day(stats: &mut StatsState, day: i32) -> (Vec<String>, bool) {
+        let mut messages = Vec::new();
+        let events = stats.process_new_day(day, 30, &mut |event: LogEvent| messages.push(event.message));
+        let is_game_over = events.iter().any(|event| match event {
+            GameEvent::RefreshActivities => true,
+            _ => false,
+        });
+
+        (messages, is_game_over)
+    }
+
+    #[test]
+    fn starving_to_death() {
+        let mut stats = StatsState::new();
+        stats.add(Stat::Food, -100);
+
+        let (_, is_game_over) = new_day(&mut stats, 1);
+        assert!(!is_game_over);
+        assert!(stats.condition(GameCondition::Starving));
+
+        let (messages, is_game_over) = new_day(&mut stats, 2);
+        assert!(is_game_over);
+        assert!(stats.condition(GameCondition::GameOver));
+        assert_eq!(stats.game_over_reason, Some(GameOverReason::Starved));
+        assert!(messages.iter().any(|msg| msg.contains("starvation")));
+    }
+
+    #[test]
+    fn eating_ends_starvation() {
+        let mut stats = StatsState::new();
+        stats.set_condition(GameCondition::Starving, true);
+
+        let (_, is_game_over) = new_day(&mut stats, 1);
+        assert!(!is_game_over);
+        assert!(!stats.condition(GameCondition::Starving));
+    }
+
+    #[test]
+    fn insanity_to_death() {
+        let mut stats = StatsState::new();
+        stats.add(Stat::Sanity, -100);
+
+        let (_, is_game_over) = new_day(&mut stats, 1);
+        assert!(!is_game_over);
+        assert!(stats.condition(GameCondition::Insane));
+
+        let (_, is_game_over) = new_day(&mut stats, 2);
+        assert!(is_game_over);
+        assert_eq!(stats.game_over_reason, Some(GameOverReason::Insane));
+    }
+
+    #[test]
+    fn paycheck_every_five_days() {
+        let mut stats = StatsState::new();
+        for day in 1..DAYS_PER_PAYCHECK {
+            // Keep the generator fueled, so the lighthouse stays on
+            stats.set_condition(GameCondition::GeneratorOutOfGas, false);
+            new_day(&mut stats, day);
+            assert_eq!(stats.stat(Stat::Money), 5);
+        }
+
+        stats.set_condition(GameCondition::GeneratorOutOfGas, false);
+        let (messages, _) = new_day(&mut stats, DAYS_PER_PAYCHECK);
+        assert_eq!(stats.stat(Stat::Money), 5 + (PAY_PER_NIGHT * DAYS_PER_PAYCHECK));
+        assert!(messages.iter().any(|msg| msg.contains("paycheck")));
+    }
+
+    #[test]
+    fn pay_docked_for_broken_lighthouse() {
+        let mut stats = StatsState::new();
+        stats.set_condition(GameCondition::LensBroken, true);
+
+        let (messages, _) = new_day(&mut stats, 1);
+        assert_eq!(stats.money_earned, 0);
+        assert!(messages.iter().any(|msg| msg.contains("docked")));
+    }
+
+    fn fishing_trips(seed: u64, time_of_day: TimeOfDay, weather: &WeatherState, count: usize) -> Vec<FishingResult> {
+        let mut rng = GameRng::new(Some(seed));
+        (0..count).map(|_| fishing_outcome(&mut rng, time_of_day, weather)).collect()
+    }
+
+    #[test]
+    fn fishing_outcomes_morning_clear() {
+        use FishingResult::*;
+
+        let trips = fishing_trips(42, TimeOfDay::Morning, &WeatherState::new(), 8);
+        assert_eq!(trips, vec![NoBites, NoBites, NoBites, Fish, HugeFish, Fish, NoBites, Fish]);
+    }
+
+    #[test]
+    fn fishing_outcomes_night() {
+        use FishingResult::*;
+
+        // Same rolls as the morning, but the fish that bit at 0.41 stay away at night
+        let trips = fishing_trips(42, TimeOfDay::Night, &WeatherState::new(), 8);
+        assert_eq!(trips, vec![NoBites, NoBites, NoBites, NoBites, HugeFish, Fish, NoBites, Fish]);
+    }
+
+    #[test]
+    fn fishing_outcomes_storm() {
+        use FishingResult::*;
+
+        let storm = WeatherState {
+            kind: Weather::Storm,
+            intensity: 1.0,
+        };
+        let trips = fishing_trips(42, TimeOfDay::Morning, &storm, 8);
+        assert_eq!(trips, vec![NoBites, NoBites, NoBites, NoBites, HugeFish, Fish, NoBites, Fish]);
+    }
+}
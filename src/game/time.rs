@@ -1,7 +1,11 @@
 use crate::game::*;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+/// How many hours of activities fit into each time of day.
+pub const HOURS_PER_TIME_OF_DAY: i32 = 4;
+
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum TimeOfDay {
     Morning,
     Afternoon,
@@ -33,6 +37,26 @@ impl std::fmt::Display for TimeOfDay {
     }
 }
 
+/// The clock time for an hour into a time of day, like "8:00 AM".
+/// Morning starts at 6am, afternoon at noon, and night at 8pm.
+pub fn clock_label(time_of_day: TimeOfDay, hours_passed: i32) -> String {
+    let start_hour = match time_of_day {
+        TimeOfDay::Morning => 6,
+        TimeOfDay::Afternoon => 12,
+        TimeOfDay::Night => 20,
+    };
+
+    let hour = (start_hour + hours_passed).rem_euclid(24);
+    let suffix = if hour < 12 { "AM" } else { "PM" };
+    let display_hour = match hour % 12 {
+        0 => 12,
+        h => h,
+    };
+
+    format!("{}:00 {}", display_hour, suffix)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TimeState {
     pub day: i32,
     pub time_of_day: TimeOfDay,
@@ -47,6 +71,11 @@ impl TimeState {
             hours_passed: 0,
         }
     }
+
+    /// How many hours are left before the current time of day ends.
+    pub fn hours_remaining(&self) -> i32 {
+        HOURS_PER_TIME_OF_DAY - self.hours_passed
+    }
 }
 
 #[derive(Default)]
@@ -82,10 +111,13 @@ impl<'a> System<'a> for TimeSystem {
             .cloned()
         {
             match event {
+                GameEvent::NewGameStarted => {
+                    *time = TimeState::new();
+                }
                 GameEvent::ProgressTime { hours } => {
                     time.hours_passed += hours;
-                    if time.hours_passed >= 4 {
-                        time.hours_passed -= 4;
+                    if time.hours_passed >= HOURS_PER_TIME_OF_DAY {
+                        time.hours_passed -= HOURS_PER_TIME_OF_DAY;
                         time.time_of_day.progress();
                         did_new_time_of_day_start = true;
 
@@ -111,7 +143,7 @@ impl<'a> System<'a> for TimeSystem {
                 TimeOfDay::Night => "The darkness of night creeps upon your lonely isle.",
             }.to_owned();
 
-            log_events.single_write(LogEvent { message: msg, color: COLOR_YELLOW });
+            log_events.single_write(LogEvent { message: msg, color: COLOR_YELLOW, reveal: true });
 
             game_events.single_write(GameEvent::NewTimeOfDayStarted {
                 time_of_day: time.time_of_day,
@@ -124,14 +156,20 @@ impl<'a> System<'a> for TimeSystem {
 pub struct TimeInfoRenderSystem;
 
 impl<'a> System<'a> for TimeInfoRenderSystem {
-    type SystemData = (Write<'a, RenderState>, ReadExpect<'a, TimeState>);
+    type SystemData = (
+        Write<'a, RenderState>,
+        ReadExpect<'a, TimeState>,
+        ReadExpect<'a, GameConfig>,
+    );
+
+    fn run(&mut self, (mut render, time, config): Self::SystemData) {
+        let mut render = render.scope();
 
-    fn run(&mut self, (mut render, time): Self::SystemData) {
         // Time UI background
         render.bind_transparency(Transparency::Opaque);
         render.bind_texture(resources::TEX_SPRITESHEET_UI);
         render.bind_color(COLOR_WHITE);
-        render.bind_layer(layers::LAYER_UI);
+        render.bind_layer(layers::Layer::Ui);
         render.sprite(
             0.0,
             0.0,
@@ -216,12 +254,66 @@ impl<'a> System<'a> for TimeInfoRenderSystem {
         );
 
         // Day Text
-        render.bind_layer(layers::LAYER_UI);
+        render.bind_layer(layers::Layer::Ui);
         render.bind_transparency(Transparency::Opaque);
         render.bind_texture(resources::TEX_FONT);
         render.bind_color(COLOR_BLACK);
-        render.text(8.0, 8.0, 8, 16, 2.0, &format!("Day {}", time.day));
+        let day_text = format!("Day {}", time.day);
+        render.text(8.0, 8.0, 8, 16, 2.0, &day_text);
+
+        // Days left, after the day text
+        let days_left_text = match config.days_remaining(time.day) {
+            0 => String::from("Final day"),
+            1 => String::from("1 day left"),
+            n => format!("{} days left", n),
+        };
+        let days_left_x = 8.0 + render.measure_text(&day_text, 8, 16, 2.0).0 + 8.0;
+        render.text(days_left_x, 16.0, 8, 16, 1.0, &days_left_text);
 
         render.text(8.0, 48.0, 8, 16, 1.5, &format!("{}", time.time_of_day));
+
+        // Clock, under the hours bar, followed by the hours left in this time of day
+        let clock_text = clock_label(time.time_of_day, time.hours_passed);
+        render.text(8.0, 108.0, 8, 16, 1.0, &clock_text);
+
+        let hours_left_x = 8.0 + render.measure_text(&clock_text, 8, 16, 1.0).0 + 8.0;
+        render.text(hours_left_x, 108.0, 8, 16, 1.0, &format!("{}h left", time.hours_remaining()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_time_of_day_progresses_once() {
+        let mut world = World::new();
+        world.insert(EventChannel::<GameEvent>::new());
+        world.insert(EventChannel::<LogEvent>::new());
+        world.insert(TimeState::new());
+
+        let mut system = TimeSystem::default();
+        System::setup(&mut system, &mut world);
+        let mut reader = world.fetch_mut::<EventChannel<GameEvent>>().register_reader();
+
+        world
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .single_write(GameEvent::ProgressTime { hours: HOURS_PER_TIME_OF_DAY });
+        system.run_now(&world);
+
+        let time = world.fetch::<TimeState>();
+        assert!(time.time_of_day == TimeOfDay::Afternoon);
+        assert_eq!(time.hours_passed, 0);
+        assert_eq!(time.day, 1);
+
+        let new_times_of_day = world
+            .fetch::<EventChannel<GameEvent>>()
+            .read(&mut reader)
+            .filter(|event| match event {
+                GameEvent::NewTimeOfDayStarted { .. } => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(new_times_of_day, 1);
     }
 }
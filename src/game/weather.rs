@@ -0,0 +1,153 @@
+use crate::game::{activity::RandomHappening, rng::GameRng, *};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Weather {
+    Clear,
+    Cloudy,
+    Storm,
+    Fog,
+}
+
+impl std::fmt::Display for Weather {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let printable = match *self {
+            Weather::Clear => "Clear",
+            Weather::Cloudy => "Cloudy",
+            Weather::Storm => "Storm",
+            Weather::Fog => "Fog",
+        };
+
+        write!(f, "{}", printable)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WeatherState {
+    pub kind: Weather,
+    // How strong the weather is, from 0 to 1
+    pub intensity: f32,
+}
+
+impl WeatherState {
+    pub fn new() -> Self {
+        WeatherState {
+            kind: Weather::Clear,
+            intensity: 0.0,
+        }
+    }
+
+    /// How much the weather scales the chance of a happening.
+    /// Storms make damage to the lighthouse more likely, and clear skies make it less likely.
+    pub fn happening_chance_multiplier(&self, happening: &RandomHappening) -> f32 {
+        let is_damaging = happening.condition_effects.iter().any(|effect| match effect {
+            ConditionEffect::Set { condition } => {
+                (*condition == GameCondition::LighthouseDamaged) || (*condition == GameCondition::LensBroken)
+            }
+            _ => false,
+        });
+
+        if !is_damaging {
+            return 1.0;
+        }
+
+        match self.kind {
+            Weather::Clear => 0.5,
+            Weather::Storm => 1.0 + (2.0 * self.intensity),
+            _ => 1.0,
+        }
+    }
+
+    /// Tint for the sky and background, darker the heavier the weather.
+    pub fn background_tint(&self) -> Color {
+        match self.kind {
+            Weather::Clear => COLOR_WHITE,
+            Weather::Cloudy => Color::rgba_f32(0.85, 0.85, 0.9, 1.0),
+            Weather::Storm => {
+                let shade = 0.75 - (0.25 * self.intensity);
+                Color::rgba_f32(shade, shade, shade + 0.05, 1.0)
+            }
+            Weather::Fog => Color::rgba_f32(0.8, 0.82, 0.85, 1.0),
+        }
+    }
+}
+
+impl Default for WeatherState {
+    fn default() -> Self {
+        WeatherState::new()
+    }
+}
+
+// Chance of each kind of weather when it changes. These should add up to 1.
+const WEATHER_CHANCES: [(Weather, f32); 4] = [
+    (Weather::Clear, 0.4),
+    (Weather::Cloudy, 0.3),
+    (Weather::Fog, 0.15),
+    (Weather::Storm, 0.15),
+];
+
+#[derive(Default)]
+pub struct WeatherSystem {
+    game_event_reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for WeatherSystem {
+    type SystemData = (
+        ReadExpect<'a, EventChannel<GameEvent>>,
+        WriteExpect<'a, EventChannel<LogEvent>>,
+        WriteExpect<'a, WeatherState>,
+        WriteExpect<'a, GameRng>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+
+        self.game_event_reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (game_events, mut log_events, mut weather, mut rng): Self::SystemData) {
+        for event in game_events.read(&mut self.game_event_reader.as_mut().unwrap()) {
+            match event {
+                GameEvent::NewGameStarted => {
+                    *weather = WeatherState::new();
+                }
+                GameEvent::NewTimeOfDayStarted { .. } => {
+                    let roll: f32 = rng.gen();
+                    let mut total = 0.0;
+                    let mut kind = Weather::Clear;
+                    for (weather_kind, chance) in WEATHER_CHANCES.iter() {
+                        total += chance;
+                        if roll < total {
+                            kind = *weather_kind;
+                            break;
+                        }
+                    }
+
+                    let previous_kind = weather.kind;
+                    weather.kind = kind;
+                    weather.intensity = rng.gen_range(0.2, 1.0);
+
+                    if kind == previous_kind {
+                        continue;
+                    }
+
+                    let msg = match kind {
+                        Weather::Clear => "The skies clear over the island.",
+                        Weather::Cloudy => "Grey clouds roll in from the sea.",
+                        Weather::Storm => "A storm howls in off the sea.",
+                        Weather::Fog => "A thick fog settles over the water.",
+                    };
+
+                    log_events.single_write(LogEvent { message: String::from(msg), color: COLOR_GRAY, reveal: false });
+                }
+                _ => {}
+            }
+        }
+    }
+}
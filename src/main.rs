@@ -2,75 +2,249 @@ mod game;
 
 use game::{
     activity::*,
-    audio::{AudioAssetDb, AudioAssetId},
+    clickable::PointerState,
+    log::LogState,
+    audio::{AudioAssetDb, AudioAssetId, AudioState},
     physics::PhysicsState,
     render::RenderState,
     resources::*,
+    rng::GameRng,
+    settings::*,
     stats::*,
     time::*,
+    weather::WeatherState,
+    DebugState,
+    GameControlState,
+    GameEvent,
     GameState,
-    GameCondition,
+    ScreenState,
 };
 use gfx::{
     color::*,
     image::*,
-    input::InputState,
+    input::{InputState, VirtualKeyCode},
     renderer::*,
     texture::*,
     window::{self, *},
 };
+use shrev::EventChannel;
 use specs::prelude::*;
 
+const SAVE_FILE_PATH: &str = "save.json";
+const SETTINGS_FILE_PATH: &str = "settings.json";
+const LOG_FILE_PATH: &str = "log.txt";
+
 fn main() {
     let window_title: &str = "LD46 - Keep It Alive";
     let window_width: u32 = 1280;
     let window_height: u32 = 720;
     let render_scale: f32 = 1.0;
-    let state = GameState::new(window_width, window_height);
+    let target_fps: u32 = 60;
+    let frames_in_flight: usize = 2;
+    // Pixel art doesn't need MSAA, but it smooths out lines and anything rotated
+    let sample_count: u8 = 1;
+    let mut state = GameState::new(window_width, window_height, None);
+
+    // There won't be a settings file until a setting is changed, so keep the defaults if it's missing
+    if let Ok(settings) = Settings::read(SETTINGS_FILE_PATH) {
+        state.world.insert(settings);
+    }
+
+    println!(
+        "Starting game with seed {}",
+        state.world.read_resource::<GameRng>().seed()
+    );
 
     window::run(
-        window_title,
-        window_width,
-        window_height,
-        render_scale,
+        window::WindowConfig {
+            title: window_title,
+            icon_path: Some("res/textures/icon.png"),
+            width: window_width,
+            height: window_height,
+            render_scale,
+            is_resizable: true,
+            target_fps,
+            is_frame_rate_capped: true,
+            frames_in_flight,
+            sample_count,
+        },
         state,
         move |game, renderer| {
+            // Wait for vsync, so the game doesn't tear
+            renderer.set_present_mode(PresentMode::FIFO);
+
             // Import textures
             {
                 import_texture(
                     game::resources::TEX_COSTANZA,
                     "res/textures/costanza.png",
+                    Filter::Nearest,
                     renderer,
                 );
                 import_texture(
                     game::resources::TEX_SPRITESHEET_UI,
                     "res/textures/ui-sprites.png",
+                    Filter::Nearest,
+                    renderer,
+                );
+                let font = import_texture(
+                    game::resources::TEX_FONT,
+                    "res/textures/font.png",
+                    Filter::Nearest,
                     renderer,
                 );
-                import_texture(game::resources::TEX_FONT, "res/textures/font.png", renderer);
+                game.world.write_resource::<RenderState>().set_glyph_widths(
+                    game::resources::TEX_FONT,
+                    game::render::compute_glyph_widths(font.pixels(), font.width(), 8, 16),
+                );
+
+                // The backgrounds are scaled down to fit the window, so filter them
                 import_texture(
                     game::resources::TEX_BG_LIGHTHOUSE,
                     "res/textures/lighthouse-bg.png",
+                    Filter::Linear,
                     renderer,
                 );
                 import_texture(
                     game::resources::TEX_BG_LIGHTHOUSE_LIGHT,
                     "res/textures/lighthouse-light-bg.png",
+                    Filter::Linear,
                     renderer,
                 );
             }
 
-            // TODO
+            // Create the batches for things that aren't drawn right away, so they don't hitch the first time they show up.
+            // These are (transparency, layer, z order, shader program, texture), as bound by the code that draws them.
+            {
+                let ui = game::layers::Layer::Ui.value();
+                let overlay = game::layers::Layer::Overlay.value();
+                let tooltip = game::layers::Layer::Tooltip.value();
+                renderer.prewarm_batches(&[
+                    // Tooltip panel, outline, and text
+                    (Transparency::Transparent, tooltip, 0, 0, 0),
+                    (Transparency::Transparent, tooltip, 0, 2, 0),
+                    (Transparency::Transparent, tooltip, 1, 1, game::resources::TEX_FONT),
+                    // Pause overlay and its text
+                    (Transparency::Transparent, ui, 0, 0, 0),
+                    (Transparency::Transparent, overlay, 0, 1, game::resources::TEX_FONT),
+                    // Rain, during storms
+                    (Transparency::Transparent, game::layers::Layer::Glass.value(), 0, 1, game::resources::TEX_SPRITESHEET_UI),
+                    // Lighthouse beam, at night
+                    (Transparency::Additive, game::layers::Layer::Beam.value(), 0, 1, game::resources::TEX_BG_LIGHTHOUSE_LIGHT),
+                ]);
+            }
+
             // Import audio
-            /*
             {
                 let mut audio_db = game.world.write_resource::<AudioAssetDb>();
+                import_audio(&mut audio_db, AudioAssetId::Theme, "res/audio/theme.wav");
+                import_audio(&mut audio_db, AudioAssetId::Whispers, "res/audio/whispers.wav");
+                import_audio(&mut audio_db, AudioAssetId::Heartbeat, "res/audio/heartbeat.wav");
             }
-            */
         },
-        move |game, _window, input, dt| {
+        move |game, window, input, dt| {
+            if input.is_key_pressed(VirtualKeyCode::F5) {
+                match game.save(SAVE_FILE_PATH) {
+                    Ok(_) => println!("Saved game to {}", SAVE_FILE_PATH),
+                    Err(e) => eprintln!("Failed to save game to {}! {}", SAVE_FILE_PATH, e),
+                }
+            } else if input.is_key_pressed(VirtualKeyCode::F9) {
+                match GameState::load(SAVE_FILE_PATH, window_width, window_height) {
+                    Ok(mut state) => {
+                        // Keep the existing render state, since it holds data set up at init (like glyph widths)
+                        if let Some(render) = game.world.remove::<RenderState>() {
+                            state.world.insert(render);
+                        }
+
+                        // Settings aren't part of the save
+                        if let Some(settings) = game.world.remove::<Settings>() {
+                            state.world.insert(settings);
+                        }
+
+                        *game = state;
+                        println!("Loaded game from {}", SAVE_FILE_PATH);
+                    }
+                    Err(e) => eprintln!("Failed to load game from {}! {}", SAVE_FILE_PATH, e),
+                }
+            }
+
             game.world.insert::<InputState>(input.clone());
             game.world.insert::<DeltaTime>(dt);
+            {
+                let mut screen = game.world.write_resource::<ScreenState>();
+                screen.width = window.width;
+                screen.height = window.height;
+                screen.dpi_scale_factor = window.dpi_scale_factor;
+                screen.render_scale = window.window_scale;
+            }
+
+            if input.is_key_pressed(VirtualKeyCode::Escape) || input.is_key_pressed(VirtualKeyCode::P) {
+                let mut control = game.world.write_resource::<GameControlState>();
+                control.is_paused = !control.is_paused;
+            }
+
+            if input.is_key_pressed(VirtualKeyCode::F10) {
+                match game.world.read_resource::<LogState>().export(LOG_FILE_PATH) {
+                    Ok(_) => println!("Exported log to {}", LOG_FILE_PATH),
+                    Err(e) => eprintln!("Failed to export log to {}! {}", LOG_FILE_PATH, e),
+                }
+            }
+
+            if input.is_key_pressed(VirtualKeyCode::F12) {
+                game.world.write_resource::<GameControlState>().is_screenshot_requested = true;
+            }
+
+            if input.is_key_pressed(VirtualKeyCode::F3) {
+                let mut debug = game.world.write_resource::<DebugState>();
+                debug.draw_colliders = !debug.draw_colliders;
+            }
+
+            if input.is_key_pressed(VirtualKeyCode::F4) {
+                let mut debug = game.world.write_resource::<DebugState>();
+                debug.show_render_stats = !debug.show_render_stats;
+            }
+
+            if input.is_key_pressed(VirtualKeyCode::F8) {
+                let mut debug = game.world.write_resource::<DebugState>();
+                debug.print_stat_effects = !debug.print_stat_effects;
+            }
+
+            // Playtesting shortcuts, left out of release builds
+            if cfg!(debug_assertions) {
+                let mut game_events = game.world.write_resource::<EventChannel<GameEvent>>();
+                if input.is_key_pressed(VirtualKeyCode::F6) {
+                    // Skip ahead a whole time of day
+                    game_events.single_write(GameEvent::ProgressTime { hours: HOURS_PER_TIME_OF_DAY });
+                    game_events.single_write(GameEvent::RefreshActivities);
+                } else if input.is_key_pressed(VirtualKeyCode::F7) {
+                    game_events.single_write(GameEvent::HandleStatEffects {
+                        effects: vec![
+                            StatEffect::Add { stat: Stat::Money, amount: 10 },
+                            StatEffect::Add { stat: Stat::Parts, amount: 5 },
+                        ],
+                    });
+                    game_events.single_write(GameEvent::RefreshActivities);
+                }
+            }
+
+            // The volume controls are on the pause screen
+            if game.world.read_resource::<GameControlState>().is_paused {
+                let cursor = input.cursor_pos_logical(window.dpi_scale_factor, window.window_scale);
+                let mut settings = game.world.write_resource::<Settings>();
+                if handle_volume_controls(&mut settings, input, cursor, window.width, window.height) {
+                    game.world.write_resource::<AudioState>().apply_settings(&settings);
+
+                    if let Err(e) = settings.write(SETTINGS_FILE_PATH) {
+                        eprintln!("Failed to save settings to {}! {}", SETTINGS_FILE_PATH, e);
+                    }
+                }
+            }
+
+            // While paused, keep the last tick's render commands around so the frozen scene stays on screen
+            if game.world.read_resource::<GameControlState>().is_paused {
+                return;
+            }
+
             game.world.write_resource::<RenderState>().clear_commands();
 
             game.tick_dispatcher.dispatch(&mut game.world);
@@ -85,57 +259,129 @@ fn main() {
             }
 
             game.world.maintain();
+            game.render_dispatcher.dispatch(&mut game.world);
+            game.world.write_resource::<RenderState>().finish_tick();
+
+            window.cursor_icon = if game.world.read_resource::<PointerState>().over_clickable {
+                CursorIcon::Hand
+            } else {
+                CursorIcon::Default
+            };
+
+            let time = game.world.read_resource::<TimeState>();
+            window.title = format!("{} - Day {} - {}", window_title, time.day, time.time_of_day);
         },
         move |game, _ticks, lerp, window, renderer| {
             game.world.write_resource::<PhysicsState>().lerp = lerp;
 
+            // Anything drawn below only lives for this render, and is drawn on top of the last tick's commands
             let mut render = game.world.write_resource::<RenderState>();
 
+            // Pause overlay
+            if game.world.read_resource::<GameControlState>().is_paused {
+                render.bind_layer(game::layers::Layer::Ui);
+                render.bind_transparency(Transparency::Transparent);
+                render.rect(0.0, 0.0, window.width as f32, window.height as f32, COLOR_BLACK.with_alpha(0.5));
+
+                render.bind_color(COLOR_WHITE);
+                render.bind_layer(game::layers::Layer::Overlay);
+                render.bind_texture(game::resources::TEX_FONT);
+                render.text(
+                    (window.width as f32 / 2.0) - 48.0,
+                    (window.height as f32 / 2.0) - 16.0,
+                    8,
+                    16,
+                    2.0,
+                    "Paused",
+                );
+
+                draw_volume_controls(&mut render, &game.world.read_resource::<Settings>(), window.width, window.height);
+            }
+
             // FPS text
             let msg = format!("{}", window.fps);
             render.bind_color(COLOR_BLUE);
-            render.bind_layer(game::layers::LAYER_UI);
+            render.bind_layer(game::layers::Layer::Ui);
             render.bind_transparency(Transparency::Transparent);
             render.bind_texture(game::resources::TEX_FONT);
-            render.text(2.0, window_height as f32 - 18.0, 8, 16, 1.0, &msg);
+            render.text(2.0, window.height as f32 - 18.0, 8, 16, 1.0, &msg);
+
+            // Render stats, from the last frame since this one hasn't been processed yet
+            if game.world.read_resource::<DebugState>().show_render_stats {
+                let stats = renderer.last_frame_stats();
+                let lines = [
+                    format!("Batches: {} ({} cached)", stats.batch_count, stats.cached_batch_count),
+                    format!("Draw calls: {}", stats.draw_calls),
+                    format!("Vertices: {}", stats.vertices),
+                    format!("Sprites: {}", stats.sprites),
+                ];
+
+                for (i, line) in lines.iter().enumerate() {
+                    let y = window.height as f32 - 100.0 + (i as f32 * 18.0);
+                    render.text(2.0, y, 8, 16, 1.0, line);
+                }
+            }
+
+            // Match the clear color to the sky, for anywhere the background doesn't cover
+            let weather_tint = game.world.read_resource::<WeatherState>().background_tint();
+            let clear_color = match game.world.read_resource::<TimeState>().time_of_day {
+                TimeOfDay::Morning => Color::new(150, 190, 220, 255),
+                TimeOfDay::Afternoon => Color::new(120, 170, 210, 255),
+                TimeOfDay::Night => Color::new(15, 20, 40, 255),
+            };
+            renderer.set_clear_color(clear_color.tinted(weather_tint));
 
             // Lighthouse Background Layer
-            render.bind_color(COLOR_WHITE);
-            render.bind_layer(game::layers::LAYER_BG);
+            render.bind_color(weather_tint);
+            render.bind_layer(game::layers::Layer::Background);
             render.bind_transparency(Transparency::Opaque);
             render.bind_texture(game::resources::TEX_BG_LIGHTHOUSE);
             render.textured_quad(
-                (0.0, window_height as f32),
-                (window_width as f32, window_height as f32),
+                (0.0, window.height as f32),
+                (window.width as f32, window.height as f32),
                 (0.0, 0.0),
-                (window_width as f32, 0.0),
+                (window.width as f32, 0.0),
             );
 
             // Lighthouse light (during night)
             if game.world.read_resource::<TimeState>().time_of_day == TimeOfDay::Night {
                 // Don't do this if the StatsState says that the lighthouse isn't working
-                let stats = game.world.read_resource::<StatsState>();
-                if !stats.condition(GameCondition::LensBroken) && !stats.condition(GameCondition::GeneratorBroken) {
-                    render.bind_layer(game::layers::LAYER_BG + 1);
-                    render.bind_transparency(Transparency::Opaque);
+                if game.world.read_resource::<StatsState>().is_lighthouse_working() {
+                    render.bind_layer(game::layers::Layer::Beam);
+                    render.bind_transparency(Transparency::Additive);
                     render.bind_texture(game::resources::TEX_BG_LIGHTHOUSE_LIGHT);
                     render.textured_quad(
-                        (0.0, window_height as f32),
-                        (window_width as f32, window_height as f32),
+                        (0.0, window.height as f32),
+                        (window.width as f32, window.height as f32),
                         (0.0, 0.0),
-                        (window_width as f32, 0.0),
+                        (window.width as f32, 0.0),
                     );
                 }
             }
 
             // Process commands into batches and send to the renderer
-            let batches = renderer.process_commands(render.commands());
-            renderer.render(window.dpi_scale_factor, batches);
+            let render_commands = render.take_commands();
+            let batches = renderer.process_commands(render.tick_commands().iter().chain(render_commands.iter()));
+
+            let is_screenshot_requested = std::mem::replace(
+                &mut game.world.write_resource::<GameControlState>().is_screenshot_requested,
+                false,
+            );
+            if is_screenshot_requested {
+                match renderer.capture_frame(window.dpi_scale_factor, batches) {
+                    Some(image) => save_screenshot(&image),
+                    None => eprintln!("Failed to capture screenshot!"),
+                }
+
+                Ok(())
+            } else {
+                renderer.render(window.dpi_scale_factor, batches)
+            }
         },
     );
 }
 
-fn import_texture(id: TextureId, path: &str, renderer: &mut Renderer) -> Texture {
+fn import_texture(id: TextureId, path: &str, filter: Filter, renderer: &mut Renderer) -> Texture {
     let image: RgbaImage = gfx::image::open(path)
         .expect(&format!("Failed to open image {}!", path))
         .to_rgba();
@@ -143,7 +389,27 @@ fn import_texture(id: TextureId, path: &str, renderer: &mut Renderer) -> Texture
     let width: u32 = image.width();
     let height: u32 = image.height();
     let pixels: Vec<u8> = image.into_raw();
-    renderer.create_gpu_texture(id, width, height, &pixels);
+    renderer.create_gpu_texture(id, width, height, &pixels, filter, WrapMode::Tile);
 
     Texture::new(id, width, height, pixels)
 }
+
+fn save_screenshot(image: &RgbaImage) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = format!("screenshot-{}.png", timestamp);
+
+    match image.save(&path) {
+        Ok(_) => println!("Saved screenshot to {}", path),
+        Err(e) => eprintln!("Failed to save screenshot {}! {}", path, e),
+    }
+}
+
+fn import_audio(audio_db: &mut AudioAssetDb, id: AudioAssetId, path: &str) {
+    // Missing audio isn't fatal, the game just plays without it
+    if let Err(e) = audio_db.import(id, path) {
+        eprintln!("Failed to import audio {}! {}", path, e);
+    }
+}